@@ -0,0 +1,181 @@
+//! Writing of BGZF compressed data as used by BAM and block compressed VCF files
+
+use flate2::Compression;
+use flate2::Crc;
+use flate2::write::DeflateEncoder;
+use std::io;
+use std::io::Write;
+use std::thread;
+
+/// Maximum number of uncompressed bytes in a single BGZF block
+const BLOCK_SIZE: usize = 0xff00;
+
+/// Empty BGZF block used to mark the end of file
+const EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Number of blocks compressed in parallel by each thread
+const BLOCKS_PER_THREAD: usize = 4;
+
+/// Writes BGZF compressed data as used by BAM files or block compressed VCF files.
+///
+/// Blocks are independent of each other, so if more than one thread is used, multiple
+/// blocks are compressed in parallel and written in original order.
+///
+/// Call `finish()` to write remaining data and the BGZF end-of-file marker.
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    level: Compression,
+    threads: usize,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    /// Creates a writer using given compression level and number of compressing threads
+    pub fn new(inner: W, level: Compression, threads: usize) -> Self {
+        let threads = threads.max(1);
+        BgzfWriter {
+            inner,
+            buf: Vec::with_capacity(BLOCK_SIZE * BLOCKS_PER_THREAD * threads),
+            level,
+            threads,
+        }
+    }
+
+    /// Writes remaining data followed by the end-of-file marker and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush()?;
+        self.inner.write_all(&EOF_BLOCK)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Number of uncompressed bytes buffered before blocks are compressed
+    fn batch_size(&self) -> usize {
+        match self.threads {
+            1 => BLOCK_SIZE,
+            threads => BLOCK_SIZE * BLOCKS_PER_THREAD * threads,
+        }
+    }
+
+    /// Compresses and writes all complete blocks or all buffered data if `all` is set
+    fn write_blocks(&mut self, all: bool) -> io::Result<()> {
+        let len = if all {
+            self.buf.len()
+        } else {
+            self.buf.len() - self.buf.len() % BLOCK_SIZE
+        };
+        let chunks = self.buf[..len].chunks(BLOCK_SIZE).collect::<Vec<_>>();
+        let level = self.level;
+
+        let blocks = if self.threads > 1 && chunks.len() > 1 {
+            let chunks_per_thread = chunks.len().div_ceil(self.threads);
+            thread::scope(|scope| {
+                chunks
+                    .chunks(chunks_per_thread)
+                    .map(|chunks| {
+                        scope.spawn(move || {
+                            chunks
+                                .iter()
+                                .map(|chunk| compress_block(chunk, level))
+                                .collect::<io::Result<Vec<_>>>()
+                        })
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .map(|handle| {
+                        handle
+                            .join()
+                            .map_err(|_| io::Error::other("Cannot compress BGZF blocks"))?
+                    })
+                    .collect::<io::Result<Vec<_>>>()
+            })?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+        } else {
+            chunks
+                .iter()
+                .map(|chunk| compress_block(chunk, level))
+                .collect::<io::Result<Vec<_>>>()?
+        };
+
+        for block in blocks {
+            self.inner.write_all(&block)?;
+        }
+        self.buf.drain(..len);
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= self.batch_size() {
+            self.write_blocks(false)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_blocks(true)?;
+        self.inner.flush()
+    }
+}
+
+/// Compresses up to `BLOCK_SIZE` bytes into a single BGZF block
+pub fn compress_block(data: &[u8], level: Compression) -> io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::with_capacity(data.len()), level);
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+
+    let mut crc = Crc::new();
+    crc.update(data);
+
+    // Total block size minus one as stored in BSIZE of the extra field
+    let block_size = (18 + compressed.len() + 8 - 1) as u16;
+
+    let mut block = Vec::with_capacity(block_size as usize + 1);
+    block.extend_from_slice(&[
+        0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02,
+        0x00,
+    ]);
+    block.extend_from_slice(&block_size.to_le_bytes());
+    block.extend_from_slice(&compressed);
+    block.extend_from_slice(&crc.sum().to_le_bytes());
+    block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bgzf::{BLOCK_SIZE, BgzfWriter};
+    use flate2::Compression;
+    use flate2::read::MultiGzDecoder;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn should_write_same_blocks_using_multiple_threads() {
+        let given = (0..BLOCK_SIZE * 5 + 123)
+            .map(|idx| b"ACGT"[idx % 7 % 4])
+            .collect::<Vec<_>>();
+
+        let compress = |threads| {
+            let mut writer = BgzfWriter::new(vec![], Compression::default(), threads);
+            writer.write_all(&given).unwrap();
+            writer.finish().unwrap()
+        };
+
+        let actual = compress(4);
+        assert_eq!(actual, compress(1));
+
+        let mut decompressed = vec![];
+        MultiGzDecoder::new(actual.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, given);
+    }
+}
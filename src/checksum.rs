@@ -0,0 +1,381 @@
+//! Checksums of file content and checksum manifest files
+
+use crate::error::Error;
+use crate::error::Error::{Io, Parse};
+use crate::metadata_file::ChecksumType;
+use crate::threaded::process_ordered;
+use crate::walk;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Reader calculating the checksum and size of all bytes read from inner reader.
+///
+/// This allows to calculate checksum and size of a file while reading its content, so
+/// each file is read only once.
+pub struct ChecksumReader<R: Read> {
+    inner: R,
+    hasher: Hasher,
+    size: u64,
+}
+
+enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Crc32c(u32),
+}
+
+impl Hasher {
+    fn new(checksum_type: &ChecksumType) -> Self {
+        match checksum_type {
+            ChecksumType::Md5 => Hasher::Md5(Md5::new()),
+            ChecksumType::Sha1 => Hasher::Sha1(Sha1::new()),
+            ChecksumType::Sha256 => Hasher::Sha256(Sha256::new()),
+            ChecksumType::Sha512 => Hasher::Sha512(Sha512::new()),
+            ChecksumType::Crc32c => Hasher::Crc32c(0),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+        }
+    }
+
+    /// Returns the lowercase hex encoded checksum
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Md5(hasher) => base16ct::lower::encode_string(&hasher.finalize()),
+            Hasher::Sha1(hasher) => base16ct::lower::encode_string(&hasher.finalize()),
+            Hasher::Sha256(hasher) => base16ct::lower::encode_string(&hasher.finalize()),
+            Hasher::Sha512(hasher) => base16ct::lower::encode_string(&hasher.finalize()),
+            Hasher::Crc32c(crc) => base16ct::lower::encode_string(&crc.to_be_bytes()),
+        }
+    }
+}
+
+impl<R: Read> ChecksumReader<R> {
+    /// Creates a reader calculating a checksum of given type
+    pub fn with_type(inner: R, checksum_type: &ChecksumType) -> Self {
+        ChecksumReader {
+            inner,
+            hasher: Hasher::new(checksum_type),
+            size: 0,
+        }
+    }
+
+    /// Reads all remaining bytes and returns the lowercase hex encoded checksum and the size
+    pub fn finish(mut self) -> io::Result<(String, u64)> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok((self.hasher.finalize(), self.size))
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+}
+
+/// Returns the checksum type of a hex encoded checksum based on its length.
+/// Checksums of 32 bits are expected to be CRC32C checksums.
+pub fn checksum_type_of(checksum: &str) -> Option<ChecksumType> {
+    match checksum.len() {
+        8 => Some(ChecksumType::Crc32c),
+        32 => Some(ChecksumType::Md5),
+        40 => Some(ChecksumType::Sha1),
+        64 => Some(ChecksumType::Sha256),
+        128 => Some(ChecksumType::Sha512),
+        _ => None,
+    }
+}
+
+/// Returns the checksum of a file, calculated by one of the worker threads of a manifest
+fn file_checksum(path: &Path, checksum_type: &ChecksumType) -> io::Result<String> {
+    File::open(path)
+        .and_then(|file| ChecksumReader::with_type(file, checksum_type).finish())
+        .map(|(checksum, _)| checksum)
+}
+
+/// Writes a manifest of all files in given directory compatible to `sha256sum` and similar
+/// tools, using paths relative to the directory. Checksums of files are calculated on given
+/// number of threads.
+pub fn write_manifest(
+    dir: &Path,
+    checksum_type: &ChecksumType,
+    threads: usize,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let files = walk(dir).map_err(|_| Io(format!("Cannot read directory '{}'", dir.display())))?;
+
+    process_ordered(
+        files.into_iter(),
+        1,
+        threads,
+        |path| {
+            let checksum = file_checksum(&path, checksum_type);
+            (path, checksum)
+        },
+        |(path, checksum)| {
+            let relative_path = path
+                .strip_prefix(dir)
+                .map_err(|_| Io("Cannot read file".to_string()))?
+                .iter()
+                .map(|component| component.to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+            let checksum =
+                checksum.map_err(|_| Io(format!("Cannot read file '{relative_path}'")))?;
+
+            // Like 'sha256sum', names containing '\' or line breaks are escaped and the line
+            // is marked by a leading '\'
+            let (marker, relative_path) = match relative_path.contains(['\\', '\n', '\r']) {
+                true => ("\\", escape(&relative_path)),
+                false => ("", relative_path),
+            };
+            writeln!(writer, "{marker}{checksum}  {relative_path}")
+                .map_err(|_| Io("Cannot write output".to_string()))
+        },
+    )?;
+
+    writer
+        .flush()
+        .map_err(|_| Io("Cannot write output".to_string()))?;
+
+    Ok(())
+}
+
+/// Verifies all files listed in a manifest created by `sha256sum` or similar tools.
+/// Paths are relative to given directory, the checksum type is detected from the checksum
+/// length if not given. Returns the result of each file and if all files are valid.
+/// Checksums of files are calculated on given number of threads.
+pub fn verify_manifest(
+    manifest: &Path,
+    dir: &Path,
+    checksum_type: Option<&ChecksumType>,
+    threads: usize,
+) -> Result<(Vec<String>, bool), Error> {
+    let content =
+        fs::read_to_string(manifest).map_err(|_| Io("Cannot read manifest file".to_string()))?;
+
+    let mut entries = vec![];
+    for (idx, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // Lines of escaped names start with '\', binary mode entries use '*' instead of
+        // second space
+        let (escaped, line) = match line.strip_prefix('\\') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (expected, path) = match line.split_once(' ') {
+            Some((checksum, path)) if path.starts_with([' ', '*']) => (checksum, &path[1..]),
+            _ => return Err(Parse(format!("Invalid manifest line {}", idx + 1))),
+        };
+        let file_path = match escaped {
+            true => unescape(path)
+                .ok_or_else(|| Parse(format!("Invalid escaped file name at line {}", idx + 1)))?,
+            false => path.to_string(),
+        };
+
+        let checksum_type = match checksum_type
+            .cloned()
+            .or_else(|| checksum_type_of(expected))
+        {
+            Some(checksum_type) => checksum_type,
+            None => return Err(Parse(format!("Unknown checksum type at line {}", idx + 1))),
+        };
+
+        entries.push((expected, path, file_path, checksum_type));
+    }
+
+    let mut results = vec![];
+    let mut valid = true;
+
+    process_ordered(
+        entries.into_iter(),
+        1,
+        threads,
+        |(expected, path, file_path, checksum_type)| {
+            let matches = file_checksum(&dir.join(file_path), &checksum_type)
+                .map(|actual| actual.eq_ignore_ascii_case(expected));
+            (path, matches)
+        },
+        |(path, matches)| {
+            match matches {
+                Ok(true) => results.push(format!("{path}: OK")),
+                Ok(false) => {
+                    results.push(format!("{path}: FAILED"));
+                    valid = false;
+                }
+                Err(_) => {
+                    results.push(format!("{path}: FAILED open or read"));
+                    valid = false;
+                }
+            }
+            Ok::<_, Error>(())
+        },
+    )?;
+
+    Ok((results, valid))
+}
+
+/// Returns the file name with '\' and line breaks escaped as done by 'sha256sum'
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Returns the unescaped file name or `None` for unknown escape sequences
+fn unescape(name: &str) -> Option<String> {
+    let mut result = String::new();
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        result.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{ChecksumReader, verify_manifest, write_manifest};
+    use crate::error::Error::Parse;
+    use crate::metadata_file::ChecksumType;
+    use crate::test_util::temp_dir;
+    use std::fs;
+    use std::io::Read;
+
+    const CHECKSUM: &str = "f2ca1bb6c7e907d06dafe4687e579fce76b37e4e93b7605022da52e6ccc26fd2";
+
+    #[test]
+    fn should_verify_manifest_with_binary_mode_and_escaped_lines() {
+        let dir = temp_dir(&[("a.txt", "test\n"), ("b\\c.txt", "test\n")]);
+        let manifest = dir.path().join("files.sha256");
+        fs::write(
+            &manifest,
+            format!("{CHECKSUM}  a.txt\n{CHECKSUM} *a.txt\n\\{CHECKSUM}  b\\\\c.txt\n{CHECKSUM}  d.txt\n"),
+        )
+        .unwrap();
+
+        let actual = verify_manifest(&manifest, dir.path(), None, 1).unwrap();
+
+        assert_eq!(
+            actual,
+            (
+                vec![
+                    "a.txt: OK".to_string(),
+                    "a.txt: OK".to_string(),
+                    "b\\\\c.txt: OK".to_string(),
+                    "d.txt: FAILED open or read".to_string(),
+                ],
+                false
+            )
+        );
+    }
+
+    #[test]
+    fn should_not_verify_manifest_with_invalid_escaped_line() {
+        let dir = temp_dir(&[("a.txt", "test\n")]);
+        let manifest = dir.path().join("files.sha256");
+        fs::write(&manifest, format!("\\{CHECKSUM}  a\\t.txt\n")).unwrap();
+
+        assert_eq!(
+            verify_manifest(&manifest, dir.path(), None, 1),
+            Err(Parse("Invalid escaped file name at line 1".to_string()))
+        );
+    }
+
+    #[test]
+    fn should_write_manifest_with_escaped_lines() {
+        let dir = temp_dir(&[("files/a.txt", "test\n"), ("files/b\nc.txt", "test\n")]);
+
+        let mut actual = vec![];
+        write_manifest(
+            &dir.path().join("files"),
+            &ChecksumType::Sha256,
+            1,
+            &mut actual,
+        )
+        .unwrap();
+        let actual = String::from_utf8(actual).unwrap();
+        assert_eq!(
+            actual,
+            format!("{CHECKSUM}  a.txt\n\\{CHECKSUM}  b\\nc.txt\n")
+        );
+
+        fs::write(dir.path().join("files.sha256"), actual).unwrap();
+        let (results, valid) = verify_manifest(
+            &dir.path().join("files.sha256"),
+            &dir.path().join("files"),
+            None,
+            1,
+        )
+        .unwrap();
+        assert_eq!(results, vec!["a.txt: OK", "b\\nc.txt: OK"]);
+        assert!(valid);
+    }
+
+    #[test]
+    fn should_calculate_checksum_of_partially_read_input() {
+        let mut reader = ChecksumReader::with_type("test\n".as_bytes(), &ChecksumType::Sha256);
+
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf).unwrap();
+
+        let (checksum, size) = reader.finish().unwrap();
+        assert_eq!(
+            checksum,
+            "f2ca1bb6c7e907d06dafe4687e579fce76b37e4e93b7605022da52e6ccc26fd2"
+        );
+        assert_eq!(size, 5);
+    }
+
+    #[test]
+    fn should_calculate_checksums_of_all_types() {
+        let checksum = |checksum_type| {
+            ChecksumReader::with_type("test\n".as_bytes(), &checksum_type)
+                .finish()
+                .unwrap()
+                .0
+        };
+
+        assert_eq!(
+            checksum(ChecksumType::Md5),
+            "d8e8fca2dc0f896fd7cb4cb0031ba249"
+        );
+        assert_eq!(
+            checksum(ChecksumType::Sha1),
+            "4e1243bd22c66e76c2ba9eddc1f91394e57f9f83"
+        );
+        assert_eq!(
+            checksum(ChecksumType::Sha512),
+            "0e3e75234abc68f4378a86b3f4b32a198ba301845b0cd6e50106e874345700cc6663a86c1ea125dc5e92be17c98f9a0f85ca9d5f595db2012f7cc3571945c123"
+        );
+        assert_eq!(checksum(ChecksumType::Crc32c), "fa94fddf");
+    }
+}
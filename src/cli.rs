@@ -1,4 +1,7 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use fastq_tools::compression::OutputCompression;
+use fastq_tools::metadata_file::ChecksumType;
+use fastq_tools::scramble_policy::HeaderField;
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -10,26 +13,238 @@ pub struct Args {
     #[arg(
         short = 'i',
         long = "input",
-        help = "Input file",
+        help = "Input file, repeat or use a glob pattern for multiple files of 'info' and 'grz-metadata'",
         group = "metadata",
+        action = ArgAction::Append,
         global = true
     )]
-    pub(crate) input_file: Option<PathBuf>,
+    pub(crate) input_files: Vec<PathBuf>,
+    /// Kept for compatibility, compressed input is detected and decompressed automatically
     #[arg(
         short = 'd',
         long = "decompress",
-        help = "Decompress input as gzip compressed data",
-        global = true
+        help = "Decompress input (ignored, compression is detected automatically)",
+        global = true,
+        hide = true
     )]
+    #[allow(dead_code)]
     pub(crate) decompress: bool,
+    #[arg(
+        short = 't',
+        long = "threads",
+        help = "Number of threads used to decompress, process and compress data",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..),
+        global = true
+    )]
+    pub(crate) threads: u16,
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 pub enum Command {
     #[command(about = "Show information about input")]
     Info,
     #[command(about = "Show GRZ metadata")]
-    GrzMetadata,
+    GrzMetadata {
+        #[arg(
+            short = '1',
+            long = "read1",
+            help = "Input file containing R1 reads of paired input",
+            requires = "read2",
+            conflicts_with = "input_files"
+        )]
+        read1: Option<PathBuf>,
+        #[arg(
+            short = '2',
+            long = "read2",
+            help = "Input file containing R2 reads of paired input",
+            requires = "read1"
+        )]
+        read2: Option<PathBuf>,
+        #[arg(
+            long = "submission-root",
+            help = "Submission root directory to show file paths relative to its 'files' directory"
+        )]
+        submission_root: Option<PathBuf>,
+        #[arg(
+            long = "long-read",
+            help = "Use long-read mode reporting the average read length, detected for Nanopore and PacBio headers",
+            conflicts_with = "read1"
+        )]
+        long_read: bool,
+        #[arg(
+            long = "checksum",
+            help = "Checksum algorithm to use",
+            default_value = "sha256"
+        )]
+        checksum: ChecksumType,
+    },
+    #[command(about = "Show GRZ submission metadata for all files of a submission")]
+    GrzSubmission {
+        #[arg(
+            long = "submission-root",
+            help = "Submission root directory containing the 'files' directory"
+        )]
+        submission_root: PathBuf,
+        #[arg(
+            long = "template",
+            help = "JSON file containing submission, donor and lab data fields"
+        )]
+        template: Option<PathBuf>,
+        #[arg(
+            long = "long-read",
+            help = "Use long-read mode for all FASTQ files, detected for Nanopore and PacBio headers"
+        )]
+        long_read: bool,
+        #[arg(
+            long = "checksum",
+            help = "Checksum algorithm to use",
+            default_value = "sha256"
+        )]
+        checksum: ChecksumType,
+    },
+    #[command(about = "Validate GRZ metadata given as input file against the actual files")]
+    GrzValidate {
+        #[arg(
+            long = "submission-root",
+            help = "Submission root directory containing the 'files' directory, defaults to parent of metadata directory"
+        )]
+        submission_root: Option<PathBuf>,
+    },
+    #[command(about = "Create or verify checksum manifest files compatible to 'sha256sum'")]
+    Checksum {
+        #[arg(
+            long = "dir",
+            help = "Directory containing the files, required to create a manifest",
+            required_unless_present = "verify"
+        )]
+        dir: Option<PathBuf>,
+        #[arg(
+            long = "verify",
+            help = "Manifest file to verify, paths are relative to '--dir' or the manifest directory"
+        )]
+        verify: Option<PathBuf>,
+        #[arg(
+            long = "checksum",
+            help = "Checksum algorithm to use, detected from checksum length when verifying if not given"
+        )]
+        checksum: Option<ChecksumType>,
+    },
     #[command(about = "Scramble input data")]
-    Scramble,
+    Scramble(ScrambleArgs),
+}
+
+#[derive(clap::Args)]
+pub struct ScrambleArgs {
+    #[arg(
+        long = "format",
+        help = "Format of input data, detected by input file extension if not set"
+    )]
+    pub(crate) format: Option<InputFormat>,
+    #[arg(
+        short = 'o',
+        long = "output",
+        help = "Output file, compressed as indicated by its extension if '--compress' is not set"
+    )]
+    pub(crate) output: Option<PathBuf>,
+    #[arg(
+        long = "compress",
+        help = "Compression of output data, detected by output file extension if not set"
+    )]
+    pub(crate) compress: Option<OutputCompression>,
+    #[arg(
+        long = "level",
+        help = "Compression level, 0-9 for gzip and BGZF and 1-22 for zstd"
+    )]
+    pub(crate) level: Option<u32>,
+    #[arg(
+        short = '1',
+        long = "read1",
+        help = "Input file containing R1 reads of paired input",
+        requires_all = ["read2", "output1", "output2"],
+        conflicts_with_all = ["input_files", "output"]
+    )]
+    pub(crate) read1: Option<PathBuf>,
+    #[arg(
+        short = '2',
+        long = "read2",
+        help = "Input file containing R2 reads of paired input",
+        requires = "read1"
+    )]
+    pub(crate) read2: Option<PathBuf>,
+    #[arg(
+        long = "output1",
+        help = "Output file for scrambled R1 reads of paired input",
+        requires = "read1"
+    )]
+    pub(crate) output1: Option<PathBuf>,
+    #[arg(
+        long = "output2",
+        help = "Output file for scrambled R2 reads of paired input",
+        requires = "read1"
+    )]
+    pub(crate) output2: Option<PathBuf>,
+    #[arg(
+        long = "reference",
+        help = "Non-sensitive reference FASTA file to sample read sequences from"
+    )]
+    pub(crate) reference: Option<PathBuf>,
+    #[arg(
+        long = "policy",
+        help = "TOML file containing policies how to scramble header fields"
+    )]
+    pub(crate) policy: Option<PathBuf>,
+    #[arg(
+        long = "keep",
+        help = "Header fields to keep unchanged",
+        value_delimiter = ','
+    )]
+    pub(crate) keep: Vec<HeaderField>,
+    #[arg(
+        long = "hash",
+        help = "Header fields to replace by values derived from SHA-256 hash",
+        value_delimiter = ','
+    )]
+    pub(crate) hash: Vec<HeaderField>,
+    #[arg(
+        long = "constant",
+        help = "Header field to replace by a constant value, e.g. 'instrument-name=TEST01'",
+        value_name = "FIELD=VALUE"
+    )]
+    pub(crate) constant: Vec<String>,
+    #[arg(
+        long = "window",
+        help = "Window size to move positions of VCF records within",
+        default_value_t = 100
+    )]
+    pub(crate) window: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum InputFormat {
+    Fastq,
+    Sam,
+    Bam,
+    Vcf,
+}
+
+impl InputFormat {
+    /// Returns input format detected by file extension or FASTQ as default
+    pub fn from_path(path: &Option<PathBuf>) -> Self {
+        let path = match path {
+            Some(path) => path.to_string_lossy().to_lowercase(),
+            None => return InputFormat::Fastq,
+        };
+
+        if path.ends_with(".bam") {
+            InputFormat::Bam
+        } else if path.ends_with(".sam") || path.ends_with(".sam.gz") {
+            InputFormat::Sam
+        } else if path.ends_with(".vcf") || path.ends_with(".vcf.gz") {
+            InputFormat::Vcf
+        } else {
+            InputFormat::Fastq
+        }
+    }
 }
@@ -0,0 +1,259 @@
+//! Detection and decoding of compressed input and compression of output
+
+use crate::bgzf::BgzfWriter;
+use bzip2::read::MultiBzDecoder;
+use clap::ValueEnum;
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use liblzma::read::XzDecoder;
+use std::io;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::path::Path;
+
+/// Compression formats of input data detected by magic bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompressionFormat {
+    /// Uncompressed data
+    None,
+    /// Gzip compressed data including BGZF as used by BAM or block compressed VCF files
+    Gzip,
+    /// Bzip2 compressed data
+    Bzip2,
+    /// Xz compressed data
+    Xz,
+    /// Zstandard compressed data
+    Zstd,
+}
+
+impl CompressionFormat {
+    /// Returns the compression format indicated by the magic bytes at the beginning of data
+    pub fn detect(prefix: &[u8]) -> Self {
+        if prefix.starts_with(&[0x1f, 0x8b]) {
+            CompressionFormat::Gzip
+        } else if prefix.starts_with(b"BZh") {
+            CompressionFormat::Bzip2
+        } else if prefix.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            CompressionFormat::Xz
+        } else if prefix.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            CompressionFormat::Zstd
+        } else {
+            CompressionFormat::None
+        }
+    }
+
+    /// Returns a reader decoding data of this format. Concatenated gzip members, bzip2
+    /// streams, xz streams and zstd frames are read completely.
+    pub fn decoder<'a>(&self, input: impl BufRead + 'a) -> io::Result<Box<dyn BufRead + 'a>> {
+        Ok(match self {
+            CompressionFormat::None => Box::new(input),
+            CompressionFormat::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(input))),
+            CompressionFormat::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(input))),
+            CompressionFormat::Xz => Box::new(BufReader::new(XzDecoder::new_multi_decoder(input))),
+            CompressionFormat::Zstd => Box::new(BufReader::new(
+                zstd::stream::read::Decoder::with_buffer(input)?,
+            )),
+        })
+    }
+}
+
+/// Returns a reader decoding input data of any supported compression format or the input
+/// as is if it is not compressed. Only the buffered beginning of input is used to detect
+/// the format, so this works for non-seekable input like stdin as well.
+pub fn decompressed_reader<'a>(input: impl Read + 'a) -> io::Result<Box<dyn BufRead + 'a>> {
+    let mut input = BufReader::new(input);
+    let format = CompressionFormat::detect(input.fill_buf()?);
+    format.decoder(input)
+}
+
+/// Compression formats of output data
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputCompression {
+    /// No compression
+    None,
+    /// Gzip compression
+    Gzip,
+    /// Block gzip compression as used by BAM or block compressed VCF files
+    Bgzf,
+    /// Zstandard compression
+    Zstd,
+}
+
+impl OutputCompression {
+    /// Returns the compression format indicated by the file extension. VCF files are BGZF
+    /// compressed to be usable with tools like `tabix`.
+    pub fn from_path(path: &Path) -> Self {
+        let path = path.to_string_lossy().to_lowercase();
+        if path.ends_with(".vcf.gz") || path.ends_with(".bgz") {
+            OutputCompression::Bgzf
+        } else if path.ends_with(".gz") {
+            OutputCompression::Gzip
+        } else if path.ends_with(".zst") {
+            OutputCompression::Zstd
+        } else {
+            OutputCompression::None
+        }
+    }
+}
+
+/// Writer compressing output data. Call `finish()` to write remaining compressed data.
+pub enum CompressedWriter<W: Write> {
+    /// Writer of uncompressed data
+    None(W),
+    /// Writer of gzip compressed data
+    Gzip(GzEncoder<W>),
+    /// Writer of BGZF compressed data
+    Bgzf(BgzfWriter<W>),
+    /// Writer of zstd compressed data
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    /// Returns a writer using given compression level or the default level of the
+    /// compression format. Levels range from 0 to 9 for gzip and BGZF and from 1 to 22
+    /// for zstd. BGZF blocks are compressed in parallel using given number of threads.
+    pub fn new(
+        inner: W,
+        compression: OutputCompression,
+        level: Option<u32>,
+        threads: usize,
+    ) -> io::Result<Self> {
+        let max_level = match compression {
+            OutputCompression::Zstd => 22,
+            _ => 9,
+        };
+        if let Some(level) = level
+            && level > max_level
+        {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Compression level must not be greater than {max_level}"),
+            ));
+        }
+
+        let gzip_level = level.map(Compression::new).unwrap_or_default();
+
+        Ok(match compression {
+            OutputCompression::None => CompressedWriter::None(inner),
+            OutputCompression::Gzip => CompressedWriter::Gzip(GzEncoder::new(inner, gzip_level)),
+            OutputCompression::Bgzf => {
+                CompressedWriter::Bgzf(BgzfWriter::new(inner, gzip_level, threads))
+            }
+            OutputCompression::Zstd => CompressedWriter::Zstd(zstd::Encoder::new(
+                inner,
+                level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL as u32) as i32,
+            )?),
+        })
+    }
+
+    /// Writes remaining compressed data and flushes the inner writer
+    pub fn finish(self) -> io::Result<()> {
+        let mut inner = match self {
+            CompressedWriter::None(inner) => inner,
+            CompressedWriter::Gzip(encoder) => encoder.finish()?,
+            CompressedWriter::Bgzf(writer) => writer.finish()?,
+            CompressedWriter::Zstd(encoder) => encoder.finish()?,
+        };
+        inner.flush()
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CompressedWriter::None(inner) => inner.write(buf),
+            CompressedWriter::Gzip(encoder) => encoder.write(buf),
+            CompressedWriter::Bgzf(writer) => writer.write(buf),
+            CompressedWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CompressedWriter::None(inner) => inner.flush(),
+            CompressedWriter::Gzip(encoder) => encoder.flush(),
+            CompressedWriter::Bgzf(writer) => writer.flush(),
+            CompressedWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compression::{
+        CompressedWriter, CompressionFormat, OutputCompression, decompressed_reader,
+    };
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn should_read_concatenated_gzip_members() {
+        let mut data = vec![];
+        for content in ["@first\n", "@second\n"] {
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            encoder.write_all(content.as_bytes()).unwrap();
+            data.extend(encoder.finish().unwrap());
+        }
+
+        let mut actual = String::new();
+        decompressed_reader(data.as_slice())
+            .unwrap()
+            .read_to_string(&mut actual)
+            .unwrap();
+
+        assert_eq!(actual, "@first\n@second\n");
+    }
+
+    #[test]
+    fn should_decode_supported_formats() {
+        let content = "@TEST\nACGT\n+\nIIII\n";
+
+        let mut bzip2 = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        bzip2.write_all(content.as_bytes()).unwrap();
+        let mut xz = liblzma::write::XzEncoder::new(vec![], 6);
+        xz.write_all(content.as_bytes()).unwrap();
+        let zstd = zstd::encode_all(content.as_bytes(), 0).unwrap();
+
+        for (format, data) in [
+            (CompressionFormat::Bzip2, bzip2.finish().unwrap()),
+            (CompressionFormat::Xz, xz.finish().unwrap()),
+            (CompressionFormat::Zstd, zstd),
+            (CompressionFormat::None, content.as_bytes().to_vec()),
+        ] {
+            assert_eq!(CompressionFormat::detect(&data), format);
+
+            let mut actual = String::new();
+            decompressed_reader(data.as_slice())
+                .unwrap()
+                .read_to_string(&mut actual)
+                .unwrap();
+            assert_eq!(actual, content);
+        }
+    }
+
+    #[test]
+    fn should_write_and_read_compressed_output() {
+        let content = "@TEST\nACGT\n+\nIIII\n";
+
+        for compression in [
+            OutputCompression::Gzip,
+            OutputCompression::Bgzf,
+            OutputCompression::Zstd,
+        ] {
+            let mut data = vec![];
+            let mut writer = CompressedWriter::new(&mut data, compression, Some(5), 2).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+            writer.finish().unwrap();
+
+            let mut actual = String::new();
+            decompressed_reader(data.as_slice())
+                .unwrap()
+                .read_to_string(&mut actual)
+                .unwrap();
+            assert_eq!(actual, content);
+        }
+
+        assert!(CompressedWriter::new(vec![], OutputCompression::Gzip, Some(10), 1).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Errors of all operations of this library
+
+use crate::error::Error::{InvalidBed, InvalidVcf, Io, Parse, Validation};
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+
+/// Errors reading or parsing input, writing output and creating or validating GRZ metadata.
+///
+/// Each error belongs to one of the categories I/O error, parse error or validation failure,
+/// indicated by `exit_code()`. Invalid VCF and BED content is reported as parse error with
+/// the affected line, line 0 if no single line is affected.
+#[derive(PartialEq)]
+pub enum Error {
+    /// Input or output cannot be opened, read or written
+    Io(String),
+    /// Input content is invalid
+    Parse(String),
+    /// Input is valid but does not match expected values
+    Validation(String),
+    /// VCF content is invalid at given line
+    InvalidVcf(usize, String),
+    /// BED content is invalid at given line
+    InvalidBed(usize, String),
+}
+
+/// Exit code of I/O errors
+pub const EXIT_IO_ERROR: u8 = 3;
+
+/// Exit code of parse errors
+pub const EXIT_PARSE_ERROR: u8 = 4;
+
+/// Exit code of validation failures
+pub const EXIT_VALIDATION_FAILURE: u8 = 5;
+
+impl Error {
+    /// Returns the exit code of the error category
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Io(_) => EXIT_IO_ERROR,
+            Parse(_) | InvalidVcf(..) | InvalidBed(..) => EXIT_PARSE_ERROR,
+            Validation(_) => EXIT_VALIDATION_FAILURE,
+        }
+    }
+
+    /// Returns an error of same category with given message
+    pub fn with_message(&self, message: String) -> Error {
+        match self {
+            Io(_) => Io(message),
+            Parse(_) => Parse(message),
+            Validation(_) => Validation(message),
+            InvalidVcf(line, _) => InvalidVcf(*line, message),
+            InvalidBed(line, _) => InvalidBed(*line, message),
+        }
+    }
+
+    /// Returns the error of same category with given prefix added to its message.
+    /// Errors of VCF and BED content become parse errors containing the line.
+    pub fn prefixed(self, prefix: &str) -> Error {
+        match self {
+            Io(err) => Io(format!("{}: {}", prefix, err)),
+            Validation(err) => Validation(format!("{}: {}", prefix, err)),
+            err => Parse(format!("{}: {}", prefix, err)),
+        }
+    }
+}
+
+impl Debug for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Io(err) | Parse(err) | Validation(err) => write!(f, "{}", err),
+            InvalidVcf(line, err) => write!(f, "Invalid VCF file at line {}: {}", line, err),
+            InvalidBed(line, err) => write!(f, "Invalid BED file at line {}: {}", line, err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Io(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error::{InvalidVcf, Io, Parse, Validation};
+    use crate::error::{EXIT_IO_ERROR, EXIT_PARSE_ERROR, EXIT_VALIDATION_FAILURE, Error};
+    use std::io;
+
+    #[test]
+    fn should_return_exit_code_of_category() {
+        assert_eq!(Io("Cannot write output".into()).exit_code(), EXIT_IO_ERROR);
+        assert_eq!(Parse("Invalid header".into()).exit_code(), EXIT_PARSE_ERROR);
+        assert_eq!(
+            Validation("Mismatch".into()).exit_code(),
+            EXIT_VALIDATION_FAILURE
+        );
+    }
+
+    #[test]
+    fn should_keep_category_of_changed_error() {
+        let err = Validation("Mismatch".into());
+
+        assert_eq!(
+            err.with_message("Files do not match".into()),
+            Validation("Files do not match".into())
+        );
+        assert_eq!(
+            err.prefixed("file.fastq"),
+            Validation("file.fastq: Mismatch".into())
+        );
+    }
+
+    #[test]
+    fn should_keep_line_of_invalid_file_content() {
+        let err = InvalidVcf(3, "Missing ALT".into());
+
+        assert_eq!(err.exit_code(), EXIT_PARSE_ERROR);
+        assert_eq!(err.to_string(), "Invalid VCF file at line 3: Missing ALT");
+        assert_eq!(
+            err.with_message("Missing REF".into()),
+            InvalidVcf(3, "Missing REF".into())
+        );
+        assert_eq!(
+            err.prefixed("file.vcf"),
+            Parse("file.vcf: Invalid VCF file at line 3: Missing ALT".into())
+        );
+    }
+
+    #[test]
+    fn should_convert_io_errors() {
+        let err = Error::from(io::Error::other("Broken pipe"));
+
+        assert_eq!(err, Io("Broken pipe".into()));
+    }
+}
@@ -1,18 +1,29 @@
+//! Parsing of FASTQ records and headers
+
+use crate::error::Error;
+use crate::error::Error::{Io, Parse};
+use crate::scramble_policy::ScramblePolicy;
 use crate::scramble_sequence;
 use serde::Serialize;
+use std::borrow::Cow;
 use std::fmt::Display;
+use std::io::BufRead;
 use std::str::FromStr;
 use std::string::ToString;
 
-pub enum Header {
-    Casava18(Casava18Header),
-    Illumina(IlluminaHeader),
+/// Header of a FASTQ record. String fields borrow from the parsed header line if possible.
+pub enum Header<'a> {
+    /// Header of Casava 1.8+
+    Casava18(Casava18Header<'a>),
+    /// Header of Illumina before Casava 1.8
+    Illumina(IlluminaHeader<'a>),
 }
 
-pub struct Casava18Header {
-    instrument_name: String,
+/// Header of Casava 1.8+ like `@<instrument>:<run>:<flowcell>:<lane>:<tile>:<x>:<y> <read>:<filtered>:<control>:<index>`
+pub struct Casava18Header<'a> {
+    instrument_name: Cow<'a, str>,
     run_id: u32,
-    flowcell_id: String,
+    flowcell_id: Cow<'a, str>,
     flowcell_lane: u32,
     tile_number: u32,
     x: u32,
@@ -20,34 +31,134 @@ pub struct Casava18Header {
     pair_member: Pair,
     filtered: Filtered,
     control_bits: u32,
-    index_sequence: String,
+    index_sequence: Cow<'a, str>,
 }
 
-pub struct IlluminaHeader {
-    instrument_name: String,
+/// Header of older Illumina pipelines like `@<instrument>:<lane>:<tile>:<x>:<y>#<index>/<read>`
+pub struct IlluminaHeader<'a> {
+    instrument_name: Cow<'a, str>,
     flowcell_lane: u32,
     tile_number: u32,
     x: u32,
     y: u32,
-    index_number: String,
+    index_number: Cow<'a, str>,
     pair_member: Pair,
 }
 
-impl Header {
-    pub fn instrument_name(&self) -> String {
+impl<'a> Header<'a> {
+    /// Parses a header line containing the leading `@`, borrowing string fields from it
+    pub fn parse(s: &'a str) -> Result<Self, Error> {
+        if !s.starts_with("@") {
+            return Err(Parse("Cannot parse FASTQ header".to_string()));
+        }
+
+        let mut parts = [""; 11];
+        let mut len = 0;
+        for part in s.split([' ', '#', '/', ':']) {
+            if len == parts.len() {
+                return Err(Parse("Cannot parse FASTQ header".to_string()));
+            }
+            parts[len] = part;
+            len += 1;
+        }
+
+        fn number<T: FromStr>(value: &str, err: &str) -> Result<T, Error> {
+            value.parse::<T>().map_err(|_| Parse(err.to_string()))
+        }
+
+        if len == 11 {
+            return Ok(Header::Casava18(Casava18Header {
+                instrument_name: parts[0][1..].into(),
+                run_id: number(parts[1], "Valid Casava 1.8+ header: Number value required")?,
+                flowcell_id: parts[2].into(),
+                flowcell_lane: number(parts[3], "Valid Casava 1.8+ header: Number value required")?,
+                tile_number: number(parts[4], "Valid Casava 1.8+ header: Number value required")?,
+                x: number(parts[5], "Valid Casava 1.8+ header: Number value required")?,
+                y: number(parts[6], "Valid Casava 1.8+ header: Number value required")?,
+                pair_member: match parts[7] {
+                    "1" => Pair::PairedEnd,
+                    "2" => Pair::MatePair,
+                    _ => return Err(Parse("Invalid Casava 1.8+ header".to_string())),
+                },
+                filtered: match parts[8] {
+                    "Y" => Filtered::Y,
+                    "N" => Filtered::N,
+                    _ => return Err(Parse("Invalid Casava 1.8+ header".to_string())),
+                },
+                control_bits: match number::<u32>(
+                    parts[9],
+                    "Valid Casava 1.8+ header: Even value for control bits required",
+                )? {
+                    value if value % 2 == 0 => value,
+                    _ => return Err(Parse("Invalid Casava 1.8+ header".to_string())),
+                },
+                index_sequence: parts[10].into(),
+            }));
+        } else if len == 7 {
+            return Ok(Header::Illumina(IlluminaHeader {
+                instrument_name: parts[0][1..].into(),
+                flowcell_lane: number(parts[1], "Valid Illumina header: Number value required")?,
+                tile_number: number(parts[2], "Valid Illumina header: Number value required")?,
+                x: number(parts[3], "Valid Illumina header: Number value required")?,
+                y: number(parts[4], "Valid Illumina header: Number value required")?,
+                index_number: parts[5].into(),
+                pair_member: match parts[6] {
+                    "1" => Pair::PairedEnd,
+                    "2" => Pair::MatePair,
+                    _ => return Err(Parse("Invalid Illumina header".to_string())),
+                },
+            }));
+        }
+
+        Err(Parse("Cannot parse FASTQ header".to_string()))
+    }
+
+    /// Parses a header line given as bytes, returns an error if it is not valid UTF-8
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, Error> {
+        match std::str::from_utf8(bytes) {
+            Ok(s) => Header::parse(s),
+            Err(_) => Err(Parse("Invalid UTF-8 in FASTQ header".to_string())),
+        }
+    }
+
+    /// Returns the header owning all of its string fields
+    pub fn into_owned(self) -> Header<'static> {
+        fn owned(value: Cow<'_, str>) -> Cow<'static, str> {
+            Cow::Owned(value.into_owned())
+        }
+
         match self {
-            Header::Casava18(h) => h.instrument_name.clone(),
-            Header::Illumina(h) => h.instrument_name.clone(),
+            Header::Casava18(h) => Header::Casava18(Casava18Header {
+                instrument_name: owned(h.instrument_name),
+                flowcell_id: owned(h.flowcell_id),
+                index_sequence: owned(h.index_sequence),
+                ..h
+            }),
+            Header::Illumina(h) => Header::Illumina(IlluminaHeader {
+                instrument_name: owned(h.instrument_name),
+                index_number: owned(h.index_number),
+                ..h
+            }),
         }
     }
 
-    pub fn flowcell_id(&self) -> Option<String> {
+    /// Returns the instrument name without the leading `@`
+    pub fn instrument_name(&self) -> &str {
         match self {
-            Header::Casava18(h) => Some(h.flowcell_id.clone()),
+            Header::Casava18(h) => &h.instrument_name,
+            Header::Illumina(h) => &h.instrument_name,
+        }
+    }
+
+    /// Returns the flowcell ID, not available in Illumina headers
+    pub fn flowcell_id(&self) -> Option<&str> {
+        match self {
+            Header::Casava18(h) => Some(&h.flowcell_id),
             Header::Illumina(_) => None,
         }
     }
 
+    /// Returns the flowcell lane number
     pub fn flowcell_lane(&self) -> u32 {
         match self {
             Header::Casava18(h) => h.flowcell_lane,
@@ -55,6 +166,7 @@ impl Header {
         }
     }
 
+    /// Returns the member of a read pair, R1 or R2
     pub fn pair_member(&self) -> Pair {
         match self {
             Header::Casava18(h) => h.pair_member.clone(),
@@ -62,7 +174,38 @@ impl Header {
         }
     }
 
-    pub fn scramble(self) -> Self {
+    /// Returns `true` if both headers belong to the two reads of the same cluster
+    pub fn is_mate_of(&self, other: &Header<'_>) -> bool {
+        match (self, other) {
+            (Header::Casava18(h), Header::Casava18(o)) => {
+                h.instrument_name == o.instrument_name
+                    && h.run_id == o.run_id
+                    && h.flowcell_id == o.flowcell_id
+                    && h.flowcell_lane == o.flowcell_lane
+                    && h.tile_number == o.tile_number
+                    && h.x == o.x
+                    && h.y == o.y
+                    && h.pair_member != o.pair_member
+            }
+            (Header::Illumina(h), Header::Illumina(o)) => {
+                h.instrument_name == o.instrument_name
+                    && h.flowcell_lane == o.flowcell_lane
+                    && h.tile_number == o.tile_number
+                    && h.x == o.x
+                    && h.y == o.y
+                    && h.pair_member != o.pair_member
+            }
+            _ => false,
+        }
+    }
+
+    /// Scrambles header fields using the default scramble policy
+    pub fn scramble(self) -> Header<'static> {
+        self.scramble_with(&ScramblePolicy::default())
+    }
+
+    /// Scrambles header fields as configured by given policy
+    pub fn scramble_with(self, policy: &ScramblePolicy) -> Header<'static> {
         fn number(value: u32) -> u32 {
             value % 3 + value % 17 + value % 271 + value % 911
         }
@@ -78,40 +221,66 @@ impl Header {
             ((value.len() as u8) + value.chars().map(|c| c as u8 & 2).sum::<u8>()) % 97
         }
 
+        fn instrument_name(value: &str) -> String {
+            format!("TEST{:0<2}", string_sum(value).wrapping_mul(17) % 97)
+        }
+
         match self {
-            Header::Casava18(header) => Header::Casava18(Casava18Header {
-                instrument_name: format!(
-                    "TEST{:0<2}",
-                    (string_sum(&header.instrument_name) * 17) % 97
-                ),
-                run_id: number(header.run_id),
-                flowcell_id: string(&header.flowcell_id),
-                flowcell_lane: number(header.flowcell_lane),
-                tile_number: number(header.tile_number),
-                x: header.x + string_sum(&header.instrument_name) as u32,
-                y: header.y + string_sum(&header.instrument_name) as u32,
-                pair_member: header.pair_member,
-                filtered: header.filtered,
-                control_bits: header.control_bits,
-                index_sequence: scramble_sequence(&header.index_sequence, 1),
-            }),
-            Header::Illumina(header) => Header::Illumina(IlluminaHeader {
-                instrument_name: format!(
-                    "TEST{:0<2}",
-                    (string_sum(&header.instrument_name) * 17) % 97
-                ),
-                flowcell_lane: number(header.flowcell_lane),
-                tile_number: number(header.tile_number),
-                x: header.x + string_sum(&header.instrument_name) as u32,
-                y: header.y + string_sum(&header.instrument_name) as u32,
-                index_number: header.index_number,
-                pair_member: header.pair_member,
-            }),
+            Header::Casava18(header) => {
+                let offset = string_sum(&header.instrument_name) as u32;
+                Header::Casava18(Casava18Header {
+                    instrument_name: policy
+                        .instrument_name
+                        .apply_string(&header.instrument_name, instrument_name)
+                        .into(),
+                    run_id: policy.run_id.apply_number(header.run_id, number),
+                    flowcell_id: policy
+                        .flowcell_id
+                        .apply_string(&header.flowcell_id, string)
+                        .into(),
+                    flowcell_lane: policy
+                        .flowcell_lane
+                        .apply_number(header.flowcell_lane, number),
+                    tile_number: policy.tile_number.apply_number(header.tile_number, number),
+                    x: policy.x.apply_number(header.x, |x| x + offset),
+                    y: policy.y.apply_number(header.y, |y| y + offset),
+                    pair_member: header.pair_member,
+                    filtered: header.filtered,
+                    control_bits: header.control_bits,
+                    index_sequence: policy
+                        .index_sequence
+                        .apply_sequence(&header.index_sequence, |value| scramble_sequence(value, 1))
+                        .into(),
+                })
+            }
+            Header::Illumina(header) => {
+                let offset = string_sum(&header.instrument_name) as u32;
+                Header::Illumina(IlluminaHeader {
+                    instrument_name: policy
+                        .instrument_name
+                        .apply_string(&header.instrument_name, instrument_name)
+                        .into(),
+                    flowcell_lane: policy
+                        .flowcell_lane
+                        .apply_number(header.flowcell_lane, number),
+                    tile_number: policy.tile_number.apply_number(header.tile_number, number),
+                    x: policy.x.apply_number(header.x, |x| x + offset),
+                    y: policy.y.apply_number(header.y, |y| y + offset),
+                    index_number: policy
+                        .index_number
+                        .apply_string(&header.index_number, |value| match value.parse() {
+                            Ok(value) => number(value).to_string(),
+                            Err(_) => string(value),
+                        })
+                        .into(),
+                    pair_member: header.pair_member,
+                })
+            }
         }
     }
 }
 
-impl Display for Header {
+impl Display for Header<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Header::Casava18(header) => {
@@ -157,110 +326,373 @@ impl Display for Header {
     }
 }
 
-impl FromStr for Header {
-    type Err = String;
+impl FromStr for Header<'static> {
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if !s.starts_with("@") {
-            return Err("Cannot parse FASTQ header".to_string());
+        Header::parse(s).map(Header::into_owned)
+    }
+}
+
+/// Header of long-read sequencing records not following Illumina conventions
+#[derive(Debug, PartialEq)]
+pub enum LongReadHeader<'a> {
+    /// Oxford Nanopore header like `@<read id> runid=<run id> ... flow_cell_id=<flowcell id>`
+    Nanopore {
+        /// Unique ID of the read
+        read_id: &'a str,
+        /// Flowcell ID if given by `flow_cell_id`
+        flowcell_id: Option<&'a str>,
+    },
+    /// PacBio header like `@<movie name>/<zmw>/ccs` or `@<movie name>/<zmw>/<start>_<end>`
+    PacBio {
+        /// Name of the movie including instrument and run
+        movie_name: &'a str,
+        /// Number of the zero-mode waveguide
+        zmw: u64,
+    },
+}
+
+impl<'a> LongReadHeader<'a> {
+    /// Parses a header line containing the leading `@`, borrowing string fields from it
+    pub fn parse(s: &'a str) -> Result<Self, Error> {
+        let mut parts = match s.strip_prefix("@") {
+            Some(s) => s.split_whitespace(),
+            None => return Err(Parse("Cannot parse FASTQ header".to_string())),
+        };
+        let read_id = parts.next().unwrap_or_default();
+
+        let is_uuid = read_id.len() == 36 && read_id.matches('-').count() == 4;
+        let mut is_nanopore = is_uuid;
+        let mut flowcell_id = None;
+        for (key, value) in parts.filter_map(|part| part.split_once('=')) {
+            match key {
+                "runid" => is_nanopore = true,
+                "flow_cell_id" => flowcell_id = flowcell_id.or(Some(value)),
+                _ => {}
+            }
+        }
+        if is_nanopore {
+            return Ok(LongReadHeader::Nanopore {
+                read_id,
+                flowcell_id,
+            });
         }
 
-        let parts = s
-            .split(" ")
-            .flat_map(|s| s.split("#").collect::<Vec<_>>())
-            .flat_map(|s| s.split("/").collect::<Vec<_>>())
-            .flat_map(|main_part| main_part.split(":").collect::<Vec<_>>())
-            .collect::<Vec<_>>();
+        let mut parts = read_id.split('/');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(movie_name), Some(zmw), Some(suffix), None)
+                if movie_name.starts_with('m')
+                    && (suffix == "ccs"
+                        || suffix.split_once('_').is_some_and(|(start, end)| {
+                            start.parse::<u64>().is_ok() && end.parse::<u64>().is_ok()
+                        })) =>
+            {
+                Ok(LongReadHeader::PacBio {
+                    movie_name,
+                    zmw: zmw.parse().map_err(|_| {
+                        Parse("Invalid PacBio header: Number value required".to_string())
+                    })?,
+                })
+            }
+            _ => Err(Parse("Cannot parse long-read FASTQ header".to_string())),
+        }
+    }
 
-        if parts.len() == 11 {
-            return Ok(Header::Casava18(Casava18Header {
-                instrument_name: parts[0][1..].to_string(),
-                run_id: parts[1]
-                    .parse()
-                    .expect("Valid Casava 1.8+ header: Number value required"),
-                flowcell_id: parts[2].into(),
-                flowcell_lane: parts[3]
-                    .parse()
-                    .expect("Valid Casava 1.8+ header: Number value required"),
-                tile_number: parts[4]
-                    .parse()
-                    .expect("Valid Casava 1.8+ header: Number value required"),
-                x: parts[5]
-                    .parse()
-                    .expect("Valid Casava 1.8+ header: Number value required"),
-                y: parts[6]
-                    .parse()
-                    .expect("Valid Casava 1.8+ header: Number value required"),
-                pair_member: match parts[7] {
-                    "1" => Pair::PairedEnd,
-                    "2" => Pair::MatePair,
-                    _ => return Err("Invalid Casava 1.8+ header".to_string()),
-                },
-                filtered: match parts[8] {
-                    "Y" => Filtered::Y,
-                    "N" => Filtered::N,
-                    _ => return Err("Invalid Casava 1.8+ header".to_string()),
-                },
-                control_bits: if parts[9]
-                    .parse::<u32>()
-                    .expect("Valid Casava 1.8+ header: Even value for control bits required")
-                    % 2
-                    == 0
-                {
-                    parts[9].parse().expect("Number")
-                } else {
-                    return Err("Invalid Casava 1.8+ header".to_string());
-                },
-                index_sequence: parts[10].into(),
-            }));
-        } else if parts.len() == 7 {
-            return Ok(Header::Illumina(IlluminaHeader {
-                instrument_name: parts[0][1..].to_string(),
-                flowcell_lane: parts[1]
-                    .parse()
-                    .expect("Valid Illumina header: Number value required"),
-                tile_number: parts[2]
-                    .parse()
-                    .expect("Valid Illumina header: Number value required"),
-                x: parts[3]
-                    .parse()
-                    .expect("Valid Illumina header: Number value required"),
-                y: parts[4]
-                    .parse()
-                    .expect("Valid Illumina header: Number value required"),
-                index_number: parts[5]
-                    .parse()
-                    .expect("Valid Illumina header: Number value required"),
-                pair_member: match parts[6] {
-                    "1" => Pair::PairedEnd,
-                    "2" => Pair::MatePair,
-                    _ => return Err("Invalid Illumina header".to_string()),
-                },
-            }));
+    /// Returns the flowcell ID of Nanopore reads or the movie name identifying the SMRT cell
+    /// of PacBio reads
+    pub fn flowcell_id(&self) -> Option<&'a str> {
+        match self {
+            LongReadHeader::Nanopore { flowcell_id, .. } => *flowcell_id,
+            LongReadHeader::PacBio { movie_name, .. } => Some(movie_name),
+        }
+    }
+}
+
+/// A single FASTQ record consisting of header, sequence, optional description and quality
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    header: String,
+    sequence: String,
+    description: String,
+    quality: String,
+}
+
+impl Record {
+    /// Parses the header line of this record
+    pub fn header(&self) -> Result<Header<'_>, Error> {
+        Header::parse(&self.header)
+    }
+
+    /// Returns the sequence line of this record
+    pub fn sequence(&self) -> &str {
+        &self.sequence
+    }
+
+    /// Returns this record using given sequence
+    pub fn with_sequence(self, sequence: String) -> Record {
+        Record { sequence, ..self }
+    }
+
+    /// Returns scrambled record with scrambled header and sequence. The optional
+    /// description is replaced by the scrambled header to not keep original header values.
+    pub fn scramble(&self, seed: u32, policy: &ScramblePolicy) -> Result<Record, Error> {
+        let header = self.header()?.scramble_with(policy).to_string();
+
+        Ok(Record {
+            description: if self.description.is_empty() {
+                String::new()
+            } else {
+                header[1..].to_string()
+            },
+            sequence: scramble_sequence(&self.sequence, seed),
+            quality: self.quality.clone(),
+            header,
+        })
+    }
+
+    /// Checks if this record is a well-formed FASTQ record
+    pub fn verify(&self) -> Result<(), Error> {
+        self.header()?;
+
+        if !self
+            .sequence
+            .chars()
+            .all(|c| c.is_ascii_alphabetic() || c == '.')
+        {
+            return Err(Parse("Invalid sequence".to_string()));
+        }
+
+        if !self.description.is_empty() && self.description != self.header[1..] {
+            return Err(Parse("Description does not match header".to_string()));
+        }
+
+        if self.quality.len() != self.sequence.len() {
+            return Err(Parse("Invalid quality string length".to_string()));
         }
 
-        Err("Cannot parse FASTQ header".to_string())
+        if !self.quality.chars().all(|c| ('!'..='~').contains(&c)) {
+            return Err(Parse("Invalid quality string".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.header)?;
+        writeln!(f, "{}", self.sequence)?;
+        writeln!(f, "+{}", self.description)?;
+        writeln!(f, "{}", self.quality)
+    }
+}
+
+/// A FASTQ record borrowing its lines from the buffer of a `RecordReader`.
+///
+/// Lines are kept as bytes and only header lines are required to be valid UTF-8 when
+/// parsed, so records can be inspected without allocating per record.
+pub struct RecordRef<'a> {
+    line: usize,
+    header: &'a [u8],
+    sequence: &'a [u8],
+    description: &'a [u8],
+    quality: &'a [u8],
+}
+
+impl<'a> RecordRef<'a> {
+    /// Returns the line number of the header line
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Parses the header line of this record
+    pub fn header(&self) -> Result<Header<'a>, Error> {
+        Header::from_bytes(self.header)
+    }
+
+    /// Parses the header line of this record as Nanopore or PacBio header
+    pub fn long_read_header(&self) -> Result<LongReadHeader<'a>, Error> {
+        match std::str::from_utf8(self.header) {
+            Ok(header) => LongReadHeader::parse(header),
+            Err(_) => Err(Parse("Invalid UTF-8 in FASTQ header".to_string())),
+        }
+    }
+
+    /// Returns the sequence line of this record
+    pub fn sequence(&self) -> &'a [u8] {
+        self.sequence
+    }
+
+    /// Returns the quality line of this record
+    pub fn quality(&self) -> &'a [u8] {
+        self.quality
+    }
+
+    /// Returns an owned copy of this record, all lines are required to be valid UTF-8
+    pub fn to_record(&self) -> Result<Record, Error> {
+        let line = |bytes: &[u8], idx: usize| match std::str::from_utf8(bytes) {
+            Ok(line) => Ok(line.to_string()),
+            Err(_) => Err(Parse(format!("Invalid UTF-8 at line {}", self.line + idx))),
+        };
+
+        Ok(Record {
+            header: line(self.header, 0)?,
+            sequence: line(self.sequence, 1)?,
+            description: line(self.description, 2)?,
+            quality: line(self.quality, 3)?,
+        })
     }
 }
 
+/// Reads FASTQ records with four lines each from buffered input.
+///
+/// Use `read_record()` to read records borrowing from a reusable buffer or use the reader
+/// as an iterator of owned records.
+pub struct RecordReader<R: BufRead> {
+    reader: R,
+    line: usize,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    /// Returns a reader of records of given input
+    pub fn new(reader: R) -> Self {
+        RecordReader {
+            reader,
+            line: 0,
+            buf: vec![],
+        }
+    }
+
+    /// Returns a reader of input starting at given line number, e.g. of a part of larger
+    /// input, so line numbers of records and errors refer to the larger input
+    pub fn starting_at_line(reader: R, line: usize) -> Self {
+        RecordReader {
+            reader,
+            line: line.saturating_sub(1),
+            buf: vec![],
+        }
+    }
+
+    /// Returns the number of lines read so far
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Reads the next line into the buffer and returns the range of the line without line
+    /// break or `None` at the end of input
+    fn read_line(&mut self) -> Result<Option<(usize, usize)>, Error> {
+        let start = self.buf.len();
+        match self.reader.read_until(b'\n', &mut self.buf) {
+            Ok(0) => Ok(None),
+            Ok(_) => {
+                self.line += 1;
+                let line = self.buf[start..]
+                    .strip_suffix(b"\n")
+                    .unwrap_or(&self.buf[start..]);
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                Ok(Some((start, start + line.len())))
+            }
+            Err(_) => Err(Io(format!("Cannot read line {}", self.line + 1))),
+        }
+    }
+
+    /// Reads the next record into the reusable buffer of this reader
+    pub fn read_record(&mut self) -> Option<Result<RecordRef<'_>, Error>> {
+        self.buf.clear();
+
+        let header = match self.read_line() {
+            Ok(Some(header)) => header,
+            Ok(None) => return None,
+            Err(err) => return Some(Err(err)),
+        };
+
+        let mut next_line = || match self.read_line() {
+            Ok(Some(line)) => Ok(line),
+            Ok(None) => Err(Parse(
+                "File contains invalid or incomplete sequences".to_string(),
+            )),
+            Err(err) => Err(err),
+        };
+
+        let record = (|| {
+            let sequence = next_line()?;
+            let description = next_line()?;
+            let quality = next_line()?;
+            Ok((sequence, description, quality))
+        })();
+
+        let (sequence, description, quality) = match record {
+            Ok(record) => record,
+            Err(err) => return Some(Err(err)),
+        };
+
+        let header_line = self.line - 3;
+        let buf = &self.buf;
+
+        if !buf[header.0..header.1].starts_with(b"@") {
+            return Some(Err(Parse(format!(
+                "Invalid header at line {}",
+                header_line
+            ))));
+        }
+
+        let description = match buf[description.0..description.1].strip_prefix(b"+") {
+            Some(description) => description,
+            None => {
+                return Some(Err(Parse(format!(
+                    "Invalid description at line {}",
+                    header_line + 2
+                ))));
+            }
+        };
+
+        Some(Ok(RecordRef {
+            line: header_line,
+            header: &buf[header.0..header.1],
+            sequence: &buf[sequence.0..sequence.1],
+            description,
+            quality: &buf[quality.0..quality.1],
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.read_record()?.and_then(|record| record.to_record()))
+    }
+}
+
+/// Member of a read pair
 #[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum Pair {
+    /// First read of a pair
     #[serde(rename = "R1")]
     PairedEnd = 1,
+    /// Second read of a pair
     #[serde(rename = "R2")]
     MatePair = 2,
 }
 
+/// Indicates if the read was filtered
 #[derive(Debug, PartialEq)]
 pub enum Filtered {
+    /// Read was filtered
     Y,
+    /// Read was not filtered
     N,
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::fastq::{Filtered, Pair};
-    use crate::{scramble_sequence, Header};
+    use crate::error::Error::Parse;
+    use crate::fastq::Header;
+    use crate::fastq::{Filtered, LongReadHeader, Pair, RecordReader};
+    use crate::scramble_policy::ScramblePolicy;
+    use crate::scramble_sequence;
 
     #[test]
     fn should_return_parsed_casava18_header() {
@@ -338,6 +770,91 @@ mod tests {
         assert_eq!(expected, actual.to_string().as_str());
     }
 
+    #[test]
+    fn should_detect_mates() {
+        let r1 = "@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG"
+            .parse::<Header>()
+            .unwrap();
+        let r2 = "@EAS139:136:FC706VJ:2:2104:15343:197393 2:Y:18:ATCACG"
+            .parse::<Header>()
+            .unwrap();
+        let other = "@EAS139:136:FC706VJ:2:2104:15343:197394 2:Y:18:ATCACG"
+            .parse::<Header>()
+            .unwrap();
+
+        assert!(r1.is_mate_of(&r2));
+        assert!(!r1.is_mate_of(&r1));
+        assert!(!r1.is_mate_of(&other));
+        assert_eq!(
+            r1.scramble().to_string().split_once(' ').unwrap().0,
+            r2.scramble().to_string().split_once(' ').unwrap().0
+        );
+    }
+
+    #[test]
+    fn should_read_records_with_quality_starting_with_at() {
+        let given =
+            "@HWUSI-EAS100R:6:73:941:1973#0/1\nGATT\n+HWUSI-EAS100R:6:73:941:1973#0/1\n@@II\n";
+        let mut reader = RecordReader::new(given.as_bytes());
+
+        let actual = reader.next().unwrap().unwrap();
+        assert_eq!(actual.sequence(), "GATT");
+        assert!(reader.next().is_none());
+
+        let scrambled = actual.scramble(1, &ScramblePolicy::default()).unwrap();
+        assert!(scrambled.verify().is_ok());
+        assert_eq!(
+            scrambled.to_string(),
+            "@TEST40:18:152:962:1994#0/1\nCGAT\n+TEST40:18:152:962:1994#0/1\n@@II\n"
+        );
+    }
+
+    #[test]
+    fn should_read_borrowed_records_and_reject_invalid_utf8() {
+        let given = b"@HWUSI-EAS100R:6:73:941:1973#0/1\r\nGATT\r\n+\r\n@@II\r\n\
+            @HWUSI-EAS100R:6:73:941:1974#0/1\xff\nCAT\n+\nIII\n";
+        let mut reader = RecordReader::new(given.as_slice());
+
+        let actual = reader.read_record().unwrap().unwrap();
+        assert_eq!(actual.line(), 1);
+        assert_eq!(actual.sequence(), b"GATT");
+        assert_eq!(actual.quality(), b"@@II");
+        assert_eq!(actual.header().unwrap().instrument_name(), "HWUSI-EAS100R");
+
+        let actual = reader.read_record().unwrap().unwrap();
+        assert_eq!(actual.line(), 5);
+        assert_eq!(actual.sequence(), b"CAT");
+        assert_eq!(
+            actual.header().err(),
+            Some(Parse("Invalid UTF-8 in FASTQ header".to_string()))
+        );
+        assert_eq!(
+            actual.to_record().err(),
+            Some(Parse("Invalid UTF-8 at line 5".to_string()))
+        );
+        assert!(reader.read_record().is_none());
+    }
+
+    #[test]
+    fn should_return_error_for_incomplete_record() {
+        let given = "@HWUSI-EAS100R:6:73:941:1973#0/1\nGATT\n+\n";
+        let mut reader = RecordReader::new(given.as_bytes());
+
+        assert!(reader.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn should_count_lines_from_given_first_line() {
+        let given = "@HWUSI-EAS100R:6:73:941:1973#0/1\nGATT\n+\nIIII\nHWUSI\nGATT\n+\nIIII\n";
+        let mut reader = RecordReader::starting_at_line(given.as_bytes(), 9);
+
+        assert_eq!(reader.read_record().unwrap().unwrap().line(), 9);
+        assert_eq!(
+            reader.read_record().unwrap().err(),
+            Some(Parse("Invalid header at line 13".to_string()))
+        );
+    }
+
     #[test]
     fn should_return_parsed_illumna_header() {
         let given = "@HWUSI-EAS100R:6:73:941:1973#0/1";
@@ -384,4 +901,29 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn should_return_parsed_long_read_headers() {
+        let nanopore = "@0c9d1a47-2b9e-4f3a-9c41-7d7f1e2a3b4c runid=abc123 read=12 ch=7 \
+            start_time=2024-01-01T10:00:00Z flow_cell_id=FAL12345";
+        let pacbio = "@m64011_190830_220126/4194378/ccs";
+
+        assert_eq!(
+            LongReadHeader::parse(nanopore),
+            Ok(LongReadHeader::Nanopore {
+                read_id: "0c9d1a47-2b9e-4f3a-9c41-7d7f1e2a3b4c",
+                flowcell_id: Some("FAL12345")
+            })
+        );
+        assert_eq!(
+            LongReadHeader::parse(pacbio),
+            Ok(LongReadHeader::PacBio {
+                movie_name: "m64011_190830_220126",
+                zmw: 4194378
+            })
+        );
+        assert!(
+            LongReadHeader::parse("@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG").is_err()
+        );
+    }
 }
@@ -0,0 +1,261 @@
+//! Library to read, inspect and scramble FASTQ, SAM/BAM and VCF files and to create and
+//! validate metadata of GRZ submissions.
+//!
+//! The `fastq-tools` application is built on top of this library, so services can use the
+//! same parsing, scrambling and GRZ metadata logic without running the application.
+//!
+//! ```
+//! use fastq_tools::fastq::RecordReader;
+//! use fastq_tools::stats::Stats;
+//!
+//! let input = "@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG\nGATT\n+\nIIII\n";
+//!
+//! let mut stats = Stats::default();
+//! let mut records = RecordReader::new(input.as_bytes());
+//! while let Some(record) = records.read_record() {
+//!     let record = record.unwrap();
+//!     stats.add_header(&record.header().unwrap());
+//!     stats.add_read_length(record.sequence().len());
+//! }
+//!
+//! assert_eq!(stats.records(), 1);
+//! assert_eq!(stats.flowcell_ids().get("FC706VJ"), Some(&1));
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod bgzf;
+pub mod checksum;
+pub mod compression;
+pub mod error;
+pub mod fastq;
+pub mod metadata_file;
+pub mod reference;
+pub mod sam;
+pub mod scramble;
+pub mod scramble_policy;
+pub mod stats;
+pub mod submission;
+pub mod threaded;
+pub mod vcf;
+
+#[cfg(test)]
+mod test_util;
+
+use crate::compression::{CompressedWriter, OutputCompression, decompressed_reader};
+use crate::error::Error;
+use crate::error::Error::{Io, Parse, Validation};
+use crate::threaded::{OutputWriter, ThreadedReader};
+use regex::Regex;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Patterns of bases followed by another base, replaced when scrambling sequences
+static AHEAD: LazyLock<[Regex; 4]> = LazyLock::new(|| {
+    [
+        Regex::new(r"T([ACG])").unwrap(),
+        Regex::new(r"A([CGT])").unwrap(),
+        Regex::new(r"G([ACT])").unwrap(),
+        Regex::new(r"C([AGT])").unwrap(),
+    ]
+});
+
+/// Scrambles a nucleotide sequence.
+///
+/// Bases are swapped and replaced depending on the seed, so the result has the same length
+/// and the same seed always results in the same scrambled sequence.
+pub fn scramble_sequence(value: &str, seed: u32) -> String {
+    let [ahead_1, ahead_2, ahead_3, ahead_4] = &*AHEAD;
+
+    let mut chars = value.chars().collect::<Vec<_>>();
+
+    (1..value.len()).for_each(|idx| {
+        if idx % (1 + (seed % 3) as usize) == 0 && chars[idx] > chars[idx - 1] {
+            chars.swap(idx, idx - 1);
+        }
+    });
+
+    let mut result = chars.iter().collect::<String>();
+
+    ahead_1.find_iter(value).for_each(|m| {
+        if !m.is_empty() {
+            result.replace_range(m.start()..m.end(), "CT")
+        }
+    });
+
+    ahead_2.find_iter(value).for_each(|m| {
+        if !m.is_empty() && !seed.is_multiple_of(2) {
+            result.replace_range(m.start()..m.end(), "GA")
+        }
+    });
+
+    ahead_3.find_iter(value).for_each(|m| {
+        if !m.is_empty() && !seed.is_multiple_of(3) {
+            result.replace_range(m.start()..m.end(), "CG")
+        }
+    });
+
+    ahead_4.find_iter(value).for_each(|m| {
+        if !m.is_empty() && !seed.is_multiple_of(5) {
+            result.replace_range(m.start()..m.end(), "GC")
+        }
+    });
+
+    result.to_string()
+}
+
+/// Returns the input files of given paths. Paths of existing files are kept as given, other
+/// paths containing `*`, `?` or `[` are expanded as shell-style glob patterns to all matching
+/// files sorted by path.
+pub fn input_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut input_files = vec![];
+    for path in paths {
+        let pattern = path.to_string_lossy();
+        if path.exists() || !pattern.contains(['*', '?', '[']) {
+            input_files.push(path.clone());
+            continue;
+        }
+
+        let matches = glob::glob(&pattern)
+            .map_err(|err| Parse(format!("Invalid input file pattern '{}': {}", pattern, err)))?
+            .filter(|entry| entry.as_ref().map_or(true, |path| path.is_file()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| Io(err.to_string()))?;
+        if matches.is_empty() {
+            return Err(Io(format!("No input file matches '{}'", pattern)));
+        }
+        input_files.extend(matches);
+    }
+    Ok(input_files)
+}
+
+/// Returns all files in given directory and its subdirectories sorted by path
+pub fn walk(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    let mut files = vec![];
+    for entry in entries {
+        if entry.is_dir() {
+            files.extend(walk(&entry)?);
+        } else {
+            files.push(entry);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Returns a reader for given input file or stdin. Compressed input is decompressed if
+/// `decompress` is set, using a separate thread if more than one thread is used.
+pub fn input_reader(
+    input_file: Option<PathBuf>,
+    decompress: bool,
+    threads: usize,
+) -> Result<Box<dyn BufRead>, Error> {
+    let input: Box<dyn Read + Send> = match input_file {
+        Some(input_file) => match File::open(input_file) {
+            Ok(file) => Box::new(file),
+            _ => {
+                return Err(Io("Cannot open input file".to_string()));
+            }
+        },
+        _ => Box::new(std::io::stdin()),
+    };
+
+    if threads > 1 {
+        Ok(Box::new(ThreadedReader::new(move || match decompress {
+            true => decompressed_reader(input),
+            false => Ok(Box::new(BufReader::new(input))),
+        })))
+    } else if decompress {
+        decompressed_reader(input).map_err(|_| Io("Cannot read input".to_string()))
+    } else {
+        Ok(Box::new(BufReader::new(input)))
+    }
+}
+
+/// Returns a buffered writer for given output file or stdout. The compression is detected
+/// by the output file extension if not given.
+pub fn output_writer(
+    output_file: Option<&PathBuf>,
+    compression: Option<OutputCompression>,
+    level: Option<u32>,
+    threads: usize,
+) -> Result<OutputWriter<Box<dyn Write + Send>>, Error> {
+    let (output, compression): (Box<dyn Write + Send>, _) = match output_file {
+        Some(output_file) => match File::create(output_file) {
+            Ok(file) => (
+                Box::new(BufWriter::new(file)),
+                compression.unwrap_or(OutputCompression::from_path(output_file)),
+            ),
+            _ => return Err(Io("Cannot create output file".to_string())),
+        },
+        None => (
+            Box::new(BufWriter::new(std::io::stdout())),
+            compression.unwrap_or(OutputCompression::None),
+        ),
+    };
+
+    CompressedWriter::new(output, compression, level, threads)
+        .map(|writer| OutputWriter::new(writer, threads))
+        .map_err(|err| match err.kind() {
+            // Invalid compression level
+            ErrorKind::InvalidInput => Validation(err.to_string()),
+            _ => Io(err.to_string()),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error::Io;
+    use crate::input_files;
+    use crate::test_util::temp_dir;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn should_expand_input_file_patterns() {
+        let dir = temp_dir(&[
+            ("sample_L002_R1_001.fastq.gz", ""),
+            ("sample_L001_R1_001.fastq.gz", ""),
+            ("sample_L001_R2_001.fastq.gz", ""),
+            ("sample_R2.fastq.gz", ""),
+        ]);
+        let dir = dir.path();
+        // Directories matching a pattern are not used as input files
+        fs::create_dir(dir.join("sample_L003_R1_001.fastq.gz")).unwrap();
+
+        let actual = input_files(&[
+            dir.join("sample_L00*_R1_001.fastq.gz"),
+            dir.join("sample_R2.fastq.gz"),
+        ]);
+
+        assert_eq!(
+            actual,
+            Ok(vec![
+                dir.join("sample_L001_R1_001.fastq.gz"),
+                dir.join("sample_L002_R1_001.fastq.gz"),
+                dir.join("sample_R2.fastq.gz"),
+            ])
+        );
+    }
+
+    #[test]
+    fn should_not_accept_patterns_without_matches() {
+        let actual = input_files(&[PathBuf::from("does-not-exist/*.fastq.gz")]);
+
+        assert_eq!(
+            actual,
+            Err(Io(
+                "No input file matches 'does-not-exist/*.fastq.gz'".into()
+            ))
+        );
+    }
+}
@@ -1,250 +1,693 @@
 mod cli;
-mod fastq;
-mod metadata_file;
 
-use crate::cli::{Args, Command};
-use crate::fastq::{Header, Pair};
-use crate::metadata_file::MetadataFile;
+use crate::cli::{Args, Command, InputFormat, ScrambleArgs};
 use clap::Parser;
+use clap::ValueEnum;
 use console::Style;
-use flate2::read::GzDecoder;
+use fastq_tools::checksum::{verify_manifest, write_manifest};
+use fastq_tools::compression::OutputCompression;
+use fastq_tools::error::EXIT_VALIDATION_FAILURE;
+use fastq_tools::error::Error;
+use fastq_tools::error::Error::{Io, Parse, Validation};
+use fastq_tools::fastq::RecordReader;
+use fastq_tools::metadata_file::{ChecksumType, MetadataFile, MetadataFileBuilder};
+use fastq_tools::reference::Reference;
+use fastq_tools::sam::{scramble_bam, scramble_sam};
+use fastq_tools::scramble::{scramble, scramble_paired};
+use fastq_tools::scramble_policy::{FieldPolicy, HeaderField, ScramblePolicy};
+use fastq_tools::stats::Stats;
+use fastq_tools::submission::Submission;
+use fastq_tools::threaded::process_ordered;
+use fastq_tools::vcf::scramble_vcf;
+use fastq_tools::{input_files, input_reader, output_writer};
 use itertools::Itertools;
-use regex::Regex;
+use std::collections::HashMap;
 use std::fmt::Display;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::fs;
+use std::hash::Hash;
+use std::io::{BufRead, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn scramble_sequence(value: &str, seed: u32) -> String {
-    let ahead_1 = Regex::new(r"T([ACG])").unwrap();
-    let ahead_2 = Regex::new(r"A([CGT])").unwrap();
-    let ahead_3 = Regex::new(r"G([ACT])").unwrap();
-    let ahead_4 = Regex::new(r"C([AGT])").unwrap();
+fn main() -> ExitCode {
+    let args = Args::parse();
 
-    let mut chars = value.chars().collect::<Vec<_>>();
+    let input_files = match input_files(&args.input_files) {
+        Ok(input_files) => input_files,
+        Err(err) => return report(err),
+    };
+    let threads = args.threads as usize;
 
-    (1..value.len()).for_each(|idx| {
-        if idx % (1 + (seed % 3) as usize) == 0 && chars[idx] > chars[idx - 1] {
-            chars.swap(idx, idx - 1);
+    // Other subcommands use a single input file
+    let input_file = match (&args.command, input_files.as_slice()) {
+        (Command::Info | Command::GrzMetadata { .. }, _) | (_, [] | [_]) => {
+            input_files.first().cloned()
         }
-    });
-
-    let mut result = chars.iter().collect::<String>();
+        _ => {
+            return report(Validation(
+                "Multiple input files are only supported by info and grz-metadata".to_string(),
+            ));
+        }
+    };
 
-    ahead_1.find_iter(value).for_each(|m| {
-        if !m.is_empty() {
-            result.replace_range(m.start()..m.end(), "CT")
+    match &args.command {
+        Command::Info => {
+            if let Err(err) = info(&input_files, threads) {
+                return report(err);
+            }
         }
-    });
+        Command::GrzMetadata {
+            read1: Some(read1),
+            read2: Some(read2),
+            submission_root,
+            checksum,
+            ..
+        } => {
+            let metadata = MetadataFile::builder()
+                .checksum_type(checksum.clone())
+                .threads(threads)
+                .read_paired_files(read1, read2)
+                .and_then(|(mut file_metadata_1, mut file_metadata_2)| {
+                    file_metadata_1.file_path = file_path(read1, submission_root)?;
+                    file_metadata_2.file_path = file_path(read2, submission_root)?;
+                    Ok([file_metadata_1, file_metadata_2])
+                });
+
+            match metadata {
+                Ok(metadata) => {
+                    println!("{}\n", serde_json::to_string_pretty(&metadata).unwrap())
+                }
+                Err(err) => {
+                    return report(err);
+                }
+            }
+        }
+        Command::GrzMetadata {
+            submission_root,
+            long_read,
+            checksum,
+            ..
+        } => {
+            let metadata_file = MetadataFile::builder()
+                .long_read(*long_read)
+                .checksum_type(checksum.clone())
+                .threads(threads);
+
+            // Repeated inputs and patterns always result in an array, even for a single file
+            let single_input =
+                matches!(args.input_files.as_slice(), [path] if input_files == [path.clone()]);
+
+            match input_files.as_slice() {
+                [input_file] if single_input => {
+                    match metadata_file
+                        .read_file(input_file)
+                        .and_then(|mut file_metadata| {
+                            file_metadata.file_path = file_path(input_file, submission_root)?;
+                            Ok(file_metadata)
+                        }) {
+                        Ok(file_metadata) => {
+                            println!(
+                                "{}\n",
+                                serde_json::to_string_pretty(&file_metadata).unwrap()
+                            );
+                            if let Some(reference_build) = file_metadata.reference_build {
+                                eprintln!("Detected reference build: {reference_build}\n");
+                            }
+                        }
+                        Err(err) => return report(err),
+                    }
+                }
+                [] => return report(Io("No input file".to_string())),
+                input_files => {
+                    if let Err(err) = grz_metadata(input_files, submission_root, &metadata_file) {
+                        return report(err);
+                    }
+                }
+            }
+        }
+        Command::GrzSubmission {
+            submission_root,
+            template,
+            long_read,
+            checksum,
+        } => {
+            let metadata = json_file(template).and_then(|template| {
+                Submission::new(submission_root.clone())
+                    .long_read(*long_read)
+                    .threads(threads)
+                    .metadata(template, checksum)
+            });
+
+            match metadata {
+                Ok(metadata) => println!("{}\n", serde_json::to_string_pretty(&metadata).unwrap()),
+                Err(err) => return report(err),
+            }
+        }
+        Command::Checksum {
+            dir,
+            verify: None,
+            checksum,
+        } => {
+            // Directory is required if no manifest file to verify is given
+            let dir = dir.clone().unwrap_or_default();
+            let checksum = checksum.clone().unwrap_or(ChecksumType::Sha256);
+            if let Err(err) = write_manifest(
+                &dir,
+                &checksum,
+                threads,
+                BufWriter::new(std::io::stdout().lock()),
+            ) {
+                return report(err);
+            }
+        }
+        Command::Checksum {
+            dir,
+            verify: Some(manifest),
+            checksum,
+        } => {
+            let dir = match dir {
+                Some(dir) => dir.clone(),
+                None => manifest
+                    .parent()
+                    .map(|parent| parent.to_path_buf())
+                    .unwrap_or_default(),
+            };
 
-    ahead_2.find_iter(value).for_each(|m| {
-        if !m.is_empty() && seed % 2 != 0 {
-            result.replace_range(m.start()..m.end(), "GA")
+            match verify_manifest(manifest, &dir, checksum.as_ref(), threads) {
+                Ok((results, valid)) => {
+                    for result in results {
+                        println!("{result}");
+                    }
+                    if !valid {
+                        eprintln!(
+                            "{}\n",
+                            Style::new()
+                                .bold()
+                                .red()
+                                .apply_to("🔥 Some checksums did not match")
+                        );
+                        return ExitCode::from(EXIT_VALIDATION_FAILURE);
+                    }
+                }
+                Err(err) => {
+                    return report(err);
+                }
+            }
         }
-    });
+        Command::GrzValidate { submission_root } => {
+            let input_file = match input_file {
+                Some(input_file) => input_file,
+                None => return report(Io("No input file".to_string())),
+            };
+
+            // Metadata is expected in '<submission root>/metadata/metadata.json'
+            let submission_root = match submission_root {
+                Some(submission_root) => submission_root.clone(),
+                None => input_file
+                    .canonicalize()
+                    .ok()
+                    .and_then(|path| Some(path.parent()?.parent()?.to_path_buf()))
+                    .unwrap_or_default(),
+            };
 
-    ahead_3.find_iter(value).for_each(|m| {
-        if !m.is_empty() && seed % 3 != 0 {
-            result.replace_range(m.start()..m.end(), "CG")
+            let mismatches = json_file(&Some(input_file)).and_then(|metadata| {
+                Submission::new(submission_root)
+                    .threads(threads)
+                    .validate(metadata.unwrap_or_default())
+            });
+
+            match mismatches {
+                Ok(mismatches) if mismatches.is_empty() => {
+                    println!(
+                        "{}\n",
+                        Style::new()
+                            .bold()
+                            .green()
+                            .apply_to("✅ Metadata matches all files")
+                    );
+                }
+                Ok(mismatches) => {
+                    for mismatch in &mismatches {
+                        eprintln!(
+                            "{}",
+                            Style::new().bold().red().apply_to(format!("🔥 {mismatch}"))
+                        );
+                    }
+                    return ExitCode::from(EXIT_VALIDATION_FAILURE);
+                }
+                Err(err) => {
+                    return report(err);
+                }
+            }
         }
-    });
+        Command::Scramble(scramble_args) => {
+            let result = match scramble_args {
+                ScrambleArgs {
+                    read1: Some(read1),
+                    read2: Some(read2),
+                    output1: Some(output1),
+                    output2: Some(output2),
+                    ..
+                } => scramble_paired_files(
+                    scramble_args,
+                    [read1, read2],
+                    [output1, output2],
+                    threads,
+                ),
+                _ => scramble_file(scramble_args, input_file, threads),
+            };
 
-    ahead_4.find_iter(value).for_each(|m| {
-        if !m.is_empty() && seed % 5 != 0 {
-            result.replace_range(m.start()..m.end(), "GC")
+            if let Err(err) = result {
+                return report(err);
+            }
         }
-    });
+    }
 
-    result.to_string()
+    ExitCode::SUCCESS
 }
 
-fn main() {
-    let args = Args::parse();
+/// Prints the error to stderr and returns the exit code of its category
+fn report(err: Error) -> ExitCode {
+    eprintln!(
+        "{}\n",
+        Style::new().bold().red().apply_to(format!("🔥 {err}"))
+    );
+    ExitCode::from(err.exit_code())
+}
 
-    let input_file = args.input_file;
+/// Prints the error of a record that could not be scrambled
+fn report_record_failure(line: usize, err: &Error) {
+    eprintln!(
+        "{}",
+        Style::new()
+            .bold()
+            .red()
+            .apply_to(format!("🔥 {err} at line {line}"))
+    );
+}
 
-    match &args.command {
-        Command::Info => match input_reader(input_file, args.decompress) {
-            Ok(input) => info(input),
-            Err(err) => {
-                eprintln!(
-                    "{}\n",
-                    Style::new().bold().red().apply_to(format!("🔥 {err}"))
-                );
-            }
-        },
-        Command::GrzMetadata => match input_file {
-            Some(input_file) => {
-                let file_metadata = match MetadataFile::read_file(input_file, args.decompress) {
-                    Ok(file_metadata) => file_metadata,
-                    Err(err) => {
-                        eprintln!(
-                            "{}\n",
-                            Style::new().bold().red().apply_to(format!("🔥 {err}"))
-                        );
-                        return;
-                    }
-                };
+/// Prints the error of a BAM record that could not be scrambled using its record number
+fn report_bam_record_failure(record: usize, err: &Error) {
+    eprintln!(
+        "{}",
+        Style::new()
+            .bold()
+            .red()
+            .apply_to(format!("🔥 {err} at record {record}"))
+    );
+}
 
-                println!(
-                    "{}\n",
-                    serde_json::to_string_pretty(&file_metadata).unwrap()
-                );
-            }
-            None => eprintln!(
-                "{}\n",
-                Style::new().bold().red().apply_to("🔥 No input file!")
-            ),
-        },
-        Command::Scramble => match input_reader(input_file, args.decompress) {
-            Ok(input) => scramble(input),
-            Err(err) => {
-                eprintln!(
-                    "{}\n",
-                    Style::new().bold().red().apply_to(format!("🔥 {err}"))
-                );
-            }
-        },
+/// Scrambles paired R1 and R2 input files into paired output files
+fn scramble_paired_files(
+    args: &ScrambleArgs,
+    [read1, read2]: [&PathBuf; 2],
+    [output1, output2]: [&PathBuf; 2],
+    threads: usize,
+) -> Result<(), Error> {
+    let format = args
+        .format
+        .unwrap_or(InputFormat::from_path(&Some(read1.clone())));
+    if format != InputFormat::Fastq {
+        return Err(Validation(
+            "Paired input is only supported for FASTQ input".into(),
+        ));
     }
+
+    let policy = scramble_policy(&args.policy, &args.keep, &args.hash, &args.constant)?;
+    let reference = reference_file(&args.reference)?;
+    let input_1 = input_reader(Some(read1.clone()), true, threads)?;
+    let input_2 = input_reader(Some(read2.clone()), true, threads)?;
+    let mut output_1 = output_writer(Some(output1), args.compress, args.level, threads)?;
+    let mut output_2 = output_writer(Some(output2), args.compress, args.level, threads)?;
+
+    scramble_paired(
+        input_1,
+        input_2,
+        &mut output_1,
+        &mut output_2,
+        reference.as_ref(),
+        &policy,
+        threads,
+        report_record_failure,
+    )?;
+
+    output_1
+        .finish()
+        .map_err(|_| Io("Cannot write output".into()))?;
+    output_2
+        .finish()
+        .map_err(|_| Io("Cannot write output".into()))
 }
 
-fn input_reader(input_file: Option<PathBuf>, decompress: bool) -> Result<Box<dyn BufRead>, String> {
-    let input: Box<dyn BufRead> = match input_file {
-        Some(input_file) => {
-            let file = match File::open(input_file) {
-                Ok(file) => file,
-                _ => {
-                    return Err("Cannot open input file".to_string());
-                }
-            };
-            Box::new(BufReader::new(file))
+/// Scrambles the input file or stdin of detected or given format
+fn scramble_file(
+    args: &ScrambleArgs,
+    input_file: Option<PathBuf>,
+    threads: usize,
+) -> Result<(), Error> {
+    let format = args.format.unwrap_or(InputFormat::from_path(&input_file));
+
+    // BAM output is always BGZF compressed by the BAM writer
+    let compress = match (format, args.compress) {
+        (InputFormat::Bam, None | Some(OutputCompression::None)) => Some(OutputCompression::None),
+        (InputFormat::Bam, Some(_)) => {
+            return Err(Validation(
+                "Output compression is not supported for BAM output".into(),
+            ));
         }
-        _ => Box::new(BufReader::new(std::io::stdin())),
+        (_, compress) => compress,
     };
 
-    let input: Box<dyn BufRead> = if decompress {
-        let gz_decoder = GzDecoder::new(input);
-        Box::new(BufReader::new(gz_decoder))
-    } else {
-        Box::new(input)
-    };
+    let mut output = output_writer(args.output.as_ref(), compress, args.level, threads)?;
+    let policy = scramble_policy(&args.policy, &args.keep, &args.hash, &args.constant)?;
+    let reference = reference_file(&args.reference)?;
+
+    match (format, reference) {
+        (InputFormat::Fastq, reference) => {
+            let input = input_reader(input_file, true, threads)?;
+            scramble(
+                input,
+                &mut output,
+                reference.as_ref(),
+                &policy,
+                threads,
+                report_record_failure,
+            )?
+        }
+        (InputFormat::Sam, None) => {
+            let input = input_reader(input_file, true, threads)?;
+            scramble_sam(input, &mut output, &policy, report_record_failure)?
+        }
+        (InputFormat::Bam, None) => {
+            let input = input_reader(input_file, false, threads)?;
+            scramble_bam(
+                input,
+                &mut output,
+                &policy,
+                threads,
+                report_bam_record_failure,
+            )?
+        }
+        (InputFormat::Vcf, reference) => {
+            let input = input_reader(input_file, true, threads)?;
+            scramble_vcf(
+                input,
+                &mut output,
+                reference.as_ref(),
+                args.window,
+                report_record_failure,
+            )?
+        }
+        _ => {
+            return Err(Validation(
+                "Reference is only supported for FASTQ and VCF input".into(),
+            ));
+        }
+    }
 
-    Ok(input)
+    output
+        .finish()
+        .map_err(|_| Io("Cannot write output".into()))
 }
 
-fn scramble(mut reader: impl BufRead) {
-    let mut buf = String::new();
-
-    let mut line = 1;
-    while let Ok(n) = reader.read_line(&mut buf) {
-        if n == 0 {
-            break;
+fn json_file(path: &Option<PathBuf>) -> Result<Option<serde_json::Value>, Error> {
+    match path {
+        Some(path) => {
+            let content =
+                fs::read_to_string(path).map_err(|_| Io("Cannot read JSON file".into()))?;
+            serde_json::from_str(&content)
+                .map(Some)
+                .map_err(|err| Parse(format!("Invalid JSON file: {err}")))
         }
+        None => Ok(None),
+    }
+}
 
-        if buf.starts_with("@") {
-            print!("{}", buf.parse::<Header>().unwrap().scramble())
-        } else if buf.starts_with("+") {
-            println!("+")
-        } else if line % 4 == 0 {
-            print!("{buf}")
-        } else if line % 4 == 2 {
-            print!("{}", scramble_sequence(&buf, line % 97))
-        }
+/// Returns the file path as given or relative to the files directory of the submission root
+fn file_path(path: &Path, submission_root: &Option<PathBuf>) -> Result<String, Error> {
+    match submission_root {
+        Some(submission_root) => Submission::new(submission_root.clone()).relative_path(path),
+        None => Ok(path.to_string_lossy().to_string()),
+    }
+}
 
-        line += 1;
-        buf.clear();
+fn reference_file(reference: &Option<PathBuf>) -> Result<Option<Reference>, Error> {
+    match reference {
+        Some(reference) => Reference::read_file(reference.clone()).map(Some),
+        None => Ok(None),
     }
 }
 
-fn info(mut reader: impl BufRead) {
-    let mut buf = String::new();
+fn scramble_policy(
+    policy_file: &Option<PathBuf>,
+    keep: &[HeaderField],
+    hash: &[HeaderField],
+    constant: &[String],
+) -> Result<ScramblePolicy, Error> {
+    let mut policy = match policy_file {
+        Some(policy_file) => ScramblePolicy::read_file(policy_file.clone())?,
+        None => ScramblePolicy::default(),
+    };
 
-    let mut headers = vec![];
-    let mut read_lens = vec![];
-    let mut quality_lens = vec![];
+    for field in keep {
+        policy.set(*field, FieldPolicy::Keep)?;
+    }
 
-    let headline_style = Style::new().bold();
-    let info_style = Style::new().bold().blue();
-    let error_style = Style::new().bold().red();
+    for field in hash {
+        policy.set(*field, FieldPolicy::Hash)?;
+    }
 
-    let mut line = 1;
-    while let Ok(n) = reader.read_line(&mut buf) {
-        if n == 0 {
-            break;
-        }
+    for constant in constant {
+        let (field, value) = match constant.split_once('=') {
+            Some((field, value)) => (HeaderField::from_str(field, true).map_err(Parse)?, value),
+            None => {
+                return Err(Parse(format!(
+                    "Invalid constant field value '{}'",
+                    constant
+                )));
+            }
+        };
+        policy.set(field, FieldPolicy::Constant(value.to_string()))?;
+    }
 
-        if buf.starts_with("@") {
-            if let Ok(header) = buf.parse::<Header>() {
-                headers.push(header)
-            } else {
-                println!(
-                    "{}",
-                    error_style.apply_to(format!("🔥 Invalid header at line {}", line))
-                );
+    Ok(policy)
+}
+
+/// Number of lines of input processed at once by `info`
+const INFO_CHUNK_LINES: usize = 16 * 1024;
+
+/// Stats and errors of consecutive records of input
+struct InfoChunk {
+    stats: Stats,
+    records: usize,
+    /// Invalid headers of records, which are skipped
+    invalid_headers: Vec<String>,
+    /// Error that stopped processing
+    error: Option<Error>,
+}
+
+impl InfoChunk {
+    /// Counts values of records in given lines of input starting at given line number
+    fn read(first_line: usize, data: &[u8]) -> Self {
+        let mut chunk = InfoChunk {
+            stats: Stats::default(),
+            records: 0,
+            invalid_headers: vec![],
+            error: None,
+        };
+
+        let mut records = RecordReader::starting_at_line(data, first_line);
+        while let Some(record) = records.read_record() {
+            let record = match record {
+                Ok(record) => record,
+                Err(err) => {
+                    chunk.error = Some(err);
+                    break;
+                }
+            };
+
+            if record.quality().len() != record.sequence().len() {
+                chunk.error = Some(Parse(format!(
+                    "Invalid quality string length at line {}",
+                    record.line() + 3
+                )));
+                break;
             }
-        } else if buf.starts_with("+") {
-            // ignore optional description
-        } else if line % 4 == 0 {
-            // check if quality values differs from sequence values
-            if Some(&buf.trim().len()) != read_lens.last() {
-                println!(
-                    "{}",
-                    error_style
-                        .apply_to(format!("🔥 Invalid quality string length at line {}", line))
-                );
-                return;
+
+            chunk.records += 1;
+            chunk.stats.add_read_length(record.sequence().len());
+            match record.header() {
+                Ok(header) => chunk.stats.add_header(&header),
+                Err(err) => chunk.invalid_headers.push(format!(
+                    "Invalid header at line {}: {}",
+                    record.line(),
+                    err
+                )),
             }
-            quality_lens.push(buf.trim().len());
-        } else if line % 4 == 2 {
-            read_lens.push(buf.trim().len());
         }
 
-        line += 1;
-        buf.clear();
+        chunk
     }
+}
 
-    if line == 1 {
-        println!("{}", error_style.apply_to("🔥 No valid input"));
-        return;
+/// Shows information about each input file and a summary of all input files, or about stdin
+/// if no input file is given
+fn info(input_files: &[PathBuf], threads: usize) -> Result<(), Error> {
+    let headline_style = Style::new().bold();
+    let info_style = Style::new().bold().blue();
+    let error_style = Style::new().bold().red();
+
+    if input_files.len() < 2 {
+        let (stats, invalid_headers) = input_reader(input_files.first().cloned(), true, threads)
+            .and_then(|input| info_stats(input, threads))?;
+        print_info(&stats);
+        return match invalid_headers {
+            0 => Ok(()),
+            _ => Err(invalid_headers_error(invalid_headers)),
+        };
     }
 
-    if line % 4 != 1 {
+    let mut summary = Stats::default();
+    let mut first_error = None;
+    let mut failed_files = 0;
+    for input_file in input_files {
         println!(
-            "{}",
-            error_style.apply_to("🔥 File contains invalid or incomplete sequences")
+            "{} {}\n",
+            info_style.apply_to("🛈 "),
+            headline_style.apply_to(format!("Input file '{}'", input_file.display()))
         );
-        return;
+
+        let stats = input_reader(Some(input_file.clone()), true, threads)
+            .and_then(|input| info_stats(input, threads))
+            .and_then(|(stats, invalid_headers)| {
+                print_info(&stats);
+                summary.merge(stats);
+                match invalid_headers {
+                    0 => Ok(()),
+                    _ => Err(invalid_headers_error(invalid_headers)),
+                }
+            });
+        if let Err(err) = stats {
+            eprintln!(
+                "{}",
+                error_style.apply_to(format!("🔥 {}: {}", input_file.display(), err))
+            );
+            failed_files += 1;
+            first_error.get_or_insert(err);
+        }
+        println!();
     }
 
     println!(
-        "{} {}",
+        "{} {}\n",
         info_style.apply_to("🛈 "),
-        headline_style.apply_to(format!("Found {} complete sequence sets", headers.len()))
+        headline_style.apply_to(format!("Summary of {} input files", input_files.len()))
     );
+    print_info(&summary);
+
+    match first_error {
+        Some(err) => Err(err.with_message(format!(
+            "{} of {} input files contain errors",
+            failed_files,
+            input_files.len()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Returns the stats of valid records of input and the number of invalid headers, which are
+/// reported and skipped. Other invalid input stops processing.
+fn info_stats(mut reader: impl BufRead, threads: usize) -> Result<(Stats, usize), Error> {
+    let error_style = Style::new().bold().red();
 
-    fn grouped_count<T>(it: impl Iterator<Item = T>) -> String
-    where
-        T: Display + Ord,
-    {
-        it.sorted()
-            .chunk_by(|value| value.to_string())
-            .into_iter()
-            .map(|g| format!("   {} ({})", g.0, g.1.count()))
-            .collect::<Vec<String>>()
-            .join("\n")
+    // Chunks contain a multiple of four lines, so all lines of a record are in the same chunk
+    let mut first_line = 1;
+    let chunks = std::iter::from_fn(|| {
+        let mut data = vec![];
+        let mut lines = 0;
+        while lines < INFO_CHUNK_LINES {
+            match reader.read_until(b'\n', &mut data) {
+                Ok(0) => break,
+                Ok(_) => lines += 1,
+                Err(_) => return Some(Err(first_line + lines)),
+            }
+        }
+        if lines == 0 {
+            return None;
+        }
+        let chunk = (first_line, data);
+        first_line += lines;
+        Some(Ok(chunk))
+    });
+
+    let mut stats = Stats::default();
+    let mut records = 0;
+    let mut invalid_headers = 0;
+    process_ordered(
+        chunks,
+        1,
+        threads,
+        |chunk| chunk.map(|(first_line, data)| InfoChunk::read(first_line, &data)),
+        |chunk| {
+            let chunk = chunk.map_err(|line| Io(format!("Cannot read line {}", line)))?;
+
+            for err in &chunk.invalid_headers {
+                eprintln!("{}", error_style.apply_to(format!("🔥 {}", err)));
+            }
+            invalid_headers += chunk.invalid_headers.len();
+            if let Some(err) = chunk.error {
+                return Err(err);
+            }
+            stats.merge(chunk.stats);
+            records += chunk.records;
+            Ok(())
+        },
+    )?;
+
+    if records == 0 {
+        return Err(Parse("No valid input".into()));
     }
 
-    // Instruments
+    Ok((stats, invalid_headers))
+}
+
+fn invalid_headers_error(invalid_headers: usize) -> Error {
+    Parse(format!(
+        "File contains {} invalid header(s)",
+        invalid_headers
+    ))
+}
+
+fn grouped_count<T>(counts: &HashMap<T, usize>) -> String
+where
+    T: Display + Ord,
+{
+    counts
+        .iter()
+        .sorted()
+        .map(|(value, count)| format!("   {} ({})", value, count))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn print_info(stats: &Stats) {
+    let headline_style = Style::new().bold();
+    let info_style = Style::new().bold().blue();
 
     println!(
         "{} {}",
         info_style.apply_to("🛈 "),
-        headline_style.apply_to("Unique instrument name(s):")
+        headline_style.apply_to(format!("Found {} complete sequence sets", stats.records()))
     );
+
+    // Instruments
+
     println!(
-        "{}",
-        grouped_count(headers.iter().map(|header| header.instrument_name()))
+        "{} {}",
+        info_style.apply_to("🛈 "),
+        headline_style.apply_to("Unique instrument name(s):")
     );
+    println!("{}", grouped_count(stats.instrument_names()));
 
     // Flowcell IDs
 
@@ -253,10 +696,7 @@ fn info(mut reader: impl BufRead) {
         info_style.apply_to("🛈 "),
         headline_style.apply_to("Flowcell ID(s):")
     );
-    println!(
-        "{}",
-        grouped_count(headers.iter().filter_map(|header| header.flowcell_id()))
-    );
+    println!("{}", grouped_count(stats.flowcell_ids()));
 
     // Flowcell Lanes
 
@@ -265,10 +705,7 @@ fn info(mut reader: impl BufRead) {
         info_style.apply_to("🛈 "),
         headline_style.apply_to("Flowcell lane(s):")
     );
-    println!(
-        "{}",
-        grouped_count(headers.iter().map(|header| header.flowcell_lane()))
-    );
+    println!("{}", grouped_count(stats.flowcell_lanes()));
 
     // Read Orders
 
@@ -277,13 +714,7 @@ fn info(mut reader: impl BufRead) {
         info_style.apply_to("🛈 "),
         headline_style.apply_to("Read order(s):")
     );
-    println!(
-        "{}",
-        grouped_count(headers.iter().map(|header| match header.pair_member() {
-            Pair::PairedEnd => "R1",
-            Pair::MatePair => "R2",
-        }))
-    );
+    println!("{}", grouped_count(stats.read_orders()));
 
     // Read Lengths
 
@@ -292,5 +723,108 @@ fn info(mut reader: impl BufRead) {
         info_style.apply_to("🛈 "),
         headline_style.apply_to("Read length(s):")
     );
-    println!("{}", grouped_count(read_lens.iter()));
+    println!("{}", grouped_count(stats.read_lengths()));
+}
+
+/// Shows GRZ metadata of all input files and a summary of all files. Files with errors are
+/// reported and omitted.
+fn grz_metadata(
+    input_files: &[PathBuf],
+    submission_root: &Option<PathBuf>,
+    metadata_file: &MetadataFileBuilder,
+) -> Result<(), Error> {
+    let headline_style = Style::new().bold();
+    let info_style = Style::new().bold().blue();
+    let error_style = Style::new().bold().red();
+
+    let mut metadata = vec![];
+    let mut first_error = None;
+    for input_file in input_files {
+        match metadata_file
+            .read_file(input_file)
+            .and_then(|mut file_metadata| {
+                file_metadata.file_path = file_path(input_file, submission_root)?;
+                Ok(file_metadata)
+            }) {
+            Ok(file_metadata) => metadata.push(file_metadata),
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    error_style.apply_to(format!("🔥 {}: {}", input_file.display(), err))
+                );
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    println!("{}\n", serde_json::to_string_pretty(&metadata).unwrap());
+
+    // Summary is written to stderr to keep JSON output usable
+    fn count<T: Eq + Hash>(counts: &mut HashMap<T, usize>, value: Option<T>) {
+        if let Some(value) = value {
+            *counts.entry(value).or_default() += 1;
+        }
+    }
+
+    fn print_counts<T: Display + Ord>(headline: &str, counts: &HashMap<T, usize>) {
+        if !counts.is_empty() {
+            eprintln!(
+                "{} {}",
+                Style::new().bold().blue().apply_to("🛈 "),
+                Style::new().bold().apply_to(headline)
+            );
+            eprintln!("{}", grouped_count(counts));
+        }
+    }
+
+    let mut flowcell_ids = HashMap::new();
+    let mut lane_ids = HashMap::new();
+    let mut read_orders = HashMap::new();
+    let mut read_lengths = HashMap::new();
+    let mut reference_builds = HashMap::new();
+    for file_metadata in &metadata {
+        count(&mut flowcell_ids, file_metadata.flowcell_id.as_ref());
+        count(&mut lane_ids, file_metadata.lane_id.as_ref());
+        count(
+            &mut read_orders,
+            file_metadata
+                .read_order
+                .as_ref()
+                .map(|read_order| format!("{:?}", read_order)),
+        );
+        count(&mut read_lengths, file_metadata.read_length);
+        count(
+            &mut reference_builds,
+            file_metadata.reference_build.as_ref(),
+        );
+    }
+
+    eprintln!(
+        "{} {}",
+        info_style.apply_to("🛈 "),
+        headline_style.apply_to(format!(
+            "Summary of {} of {} input files, {} bytes",
+            metadata.len(),
+            input_files.len(),
+            metadata
+                .iter()
+                .map(|file_metadata| file_metadata.file_size_in_bytes)
+                .sum::<u64>()
+        ))
+    );
+    print_counts("Flowcell ID(s):", &flowcell_ids);
+    print_counts("Lane ID(s):", &lane_ids);
+    print_counts("Read order(s):", &read_orders);
+    print_counts("Read length(s):", &read_lengths);
+    print_counts("Reference build(s):", &reference_builds);
+    eprintln!();
+
+    match first_error {
+        Some(err) => Err(err.with_message(format!(
+            "{} of {} input files contain errors",
+            input_files.len() - metadata.len(),
+            input_files.len()
+        ))),
+        None => Ok(()),
+    }
 }
@@ -1,17 +1,36 @@
-use crate::fastq::{Header, Pair};
-use crate::input_reader;
-use crate::metadata_file::MetadataError::{CannotReadFile, ReadError, UnsupportedFile};
-use itertools::Itertools;
+//! GRZ metadata of single files
+
+use crate::checksum::ChecksumReader;
+use crate::compression::CompressionFormat;
+use crate::error::Error;
+use crate::error::Error::{InvalidBed, InvalidVcf, Io, Parse, Validation};
+use crate::fastq::{Header, Pair, RecordReader, RecordRef};
+use crate::sam::BamReader;
+use crate::threaded::ThreadedReader;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use std::error::Error;
-use std::fmt::{Debug, Display, Formatter};
-use std::fs;
+use std::collections::{BTreeSet, HashSet};
 use std::fs::File;
-use std::io::BufRead;
-use std::path::PathBuf;
+use std::io;
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, Sender, channel};
 
-#[derive(Debug, Serialize, Deserialize)]
+/// GRZ metadata of a single file as used in the lab data entries of `metadata.json`.
+///
+/// Use `read_file()` or `read_paired_files()` to create metadata from file content or
+/// `builder()` to read files using the same options:
+///
+/// ```no_run
+/// use fastq_tools::metadata_file::{ChecksumType, MetadataFile};
+///
+/// let metadata = MetadataFile::builder()
+///     .checksum_type(ChecksumType::Sha512)
+///     .threads(4)
+///     .read_file("file.fastq.gz")?;
+/// # Ok::<(), fastq_tools::error::Error>(())
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MetadataFile {
     /// Type of checksum algorithm used
@@ -22,8 +41,8 @@ pub struct MetadataFile {
     pub file_checksum: String,
 
     /// Path relative to the submission files directory, e.g.:
-    /// 'patient_001/patient_001_dna.fastq.gz' if the file is located in <submission
-    /// root>/files/patient_001/patient_001_dna.fastq.gz
+    /// `patient_001/patient_001_dna.fastq.gz` if the file is located in
+    /// `<submission root>/files/patient_001/patient_001_dna.fastq.gz`
     pub file_path: String,
 
     /// Size of the file in bytes
@@ -48,226 +67,946 @@ pub struct MetadataFile {
     /// Indicates the read order for paired-end reads.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub read_order: Option<ReadOrder>,
+
+    /// Reference build detected from VCF or BED content, not part of GRZ file metadata.
+    #[serde(skip)]
+    pub reference_build: Option<String>,
 }
 
 /// Type of checksum algorithm used
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ValueEnum)]
 #[serde(rename_all = "snake_case")]
 pub enum ChecksumType {
+    /// MD5 checksum
+    Md5,
+    /// SHA-1 checksum
+    Sha1,
+    /// SHA-256 checksum
     Sha256,
+    /// SHA-512 checksum
+    Sha512,
+    /// CRC32C checksum
+    Crc32c,
 }
 
 /// Type of the file; if BED file is submitted, only 1 file is allowed.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum FileType {
+    /// Aligned or unaligned reads in BAM format
     Bam,
 
+    /// Regions in BED format
     Bed,
 
+    /// Reads in FASTQ format
     Fastq,
 
+    /// Variants in VCF format
     Vcf,
 }
 
 /// Indicates the read order for paired-end reads.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum ReadOrder {
+    /// First read of a pair
     R1,
 
+    /// Second read of a pair
     R2,
 }
 
-pub enum MetadataError {
-    CannotReadFile,
-    UnsupportedFile,
-    ReadError(String),
-}
-
-impl Debug for MetadataError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self)
+impl MetadataFile {
+    /// Returns a builder reading metadata of files in short-read mode using SHA-256
+    /// checksums and a single thread
+    pub fn builder() -> MetadataFileBuilder {
+        MetadataFileBuilder::default()
     }
-}
 
-impl Display for MetadataError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                CannotReadFile => "Cannot read file".into(),
-                UnsupportedFile => "Unsupported file type".into(),
-                ReadError(err) => format!("Error reading file: {}", err),
+    /// Reads metadata of given file. The file type and compression are detected from file
+    /// content, the file extension is only used if the content does not indicate a file type.
+    ///
+    /// FASTQ files of Nanopore or PacBio reads are read in long-read mode, reporting the
+    /// rounded average read length. Use `long_read` to select long-read mode for other
+    /// headers. File content is decompressed on a separate thread if more than one thread
+    /// is used.
+    pub fn read_file(
+        path: PathBuf,
+        long_read: bool,
+        checksum_type: &ChecksumType,
+        threads: usize,
+    ) -> Result<MetadataFile, Error> {
+        let (path, file_type, compression, reader) = MetadataFile::open(&path, checksum_type)?;
+
+        let (content, file_reader) = match file_type {
+            // BAM records are decompressed by the BAM reader
+            FileType::Bam => file_content(reader, CompressionFormat::None, threads)?,
+            _ => file_content(reader, compression, threads)?,
+        };
+        let metadata = match file_type {
+            FileType::Fastq => {
+                let mut fastq_metadata = FastqMetadata::new(long_read);
+                let mut records = RecordReader::new(content);
+                while let Some(record) = records.read_record() {
+                    fastq_metadata.add(&record.map_err(read_error)?)?;
+                }
+                Some(fastq_metadata.finish()?)
             }
-        )
+            FileType::Bam => Some(MetadataFile::read_bam(content)?),
+            FileType::Vcf => Some(MetadataFile::read_vcf(content)?),
+            FileType::Bed => Some(MetadataFile::read_bed(content)?),
+        };
+
+        MetadataFile::complete(path, file_type, checksum_type, file_reader, metadata)
     }
-}
 
-impl Error for MetadataError {}
+    /// Reads metadata of R1 and R2 files of paired reads and checks that both files are
+    /// mates with same number of records, matching read IDs and opposite read orders.
+    /// Content of each file is decompressed on a separate thread if more than one thread is
+    /// used.
+    pub fn read_paired_files(
+        path_1: PathBuf,
+        path_2: PathBuf,
+        checksum_type: &ChecksumType,
+        threads: usize,
+    ) -> Result<(MetadataFile, MetadataFile), Error> {
+        let (path_1, file_type_1, compression_1, reader_1) =
+            MetadataFile::open(&path_1, checksum_type)?;
+        let (path_2, file_type_2, compression_2, reader_2) =
+            MetadataFile::open(&path_2, checksum_type)?;
 
-impl MetadataFile {
-    pub fn read_file(path: PathBuf, decompress: bool) -> Result<MetadataFile, MetadataError> {
+        if file_type_1 != FileType::Fastq || file_type_2 != FileType::Fastq {
+            return Err(Parse("Unsupported file type".to_string()));
+        }
+
+        let mut fastq_metadata_1 = FastqMetadata::new(false);
+        let mut fastq_metadata_2 = FastqMetadata::new(false);
+        let (content_1, file_reader_1) = file_content(reader_1, compression_1, threads)?;
+        let (content_2, file_reader_2) = file_content(reader_2, compression_2, threads)?;
+        let mut records_1 = RecordReader::new(content_1);
+        let mut records_2 = RecordReader::new(content_2);
+
+        let mut record_number = 1;
+        loop {
+            let (record_1, record_2) = match (records_1.read_record(), records_2.read_record()) {
+                (None, None) => break,
+                (Some(record_1), Some(record_2)) => {
+                    (record_1.map_err(read_error)?, record_2.map_err(read_error)?)
+                }
+                (_, None) => {
+                    return Err(pair_mismatch(format!(
+                        "R2 file ends before record {} of R1 file",
+                        record_number
+                    )));
+                }
+                (None, _) => {
+                    return Err(pair_mismatch(format!(
+                        "R1 file ends before record {} of R2 file",
+                        record_number
+                    )));
+                }
+            };
+
+            let (Some(header_1), Some(header_2)) = (
+                fastq_metadata_1.add(&record_1)?,
+                fastq_metadata_2.add(&record_2)?,
+            ) else {
+                return Err(pair_mismatch("Long reads cannot be paired".to_string()));
+            };
+
+            if header_1.pair_member() != Pair::PairedEnd
+                || header_2.pair_member() != Pair::MatePair
+                || !header_1.is_mate_of(&header_2)
+            {
+                return Err(pair_mismatch(format!(
+                    "Record {} at line {} does not match: '{}' and '{}'",
+                    record_number,
+                    record_1.line(),
+                    header_1,
+                    header_2
+                )));
+            }
+
+            record_number += 1;
+        }
+
+        drop(records_1);
+        drop(records_2);
+
+        Ok((
+            MetadataFile::complete(
+                path_1,
+                file_type_1,
+                checksum_type,
+                file_reader_1,
+                Some(fastq_metadata_1.finish()?),
+            )?,
+            MetadataFile::complete(
+                path_2,
+                file_type_2,
+                checksum_type,
+                file_reader_2,
+                Some(fastq_metadata_2.finish()?),
+            )?,
+        ))
+    }
+
+    /// Opens the file and returns path, file type, compression format and the reader
+    /// calculating the checksum and size while reading file content
+    fn open(
+        path: &Path,
+        checksum_type: &ChecksumType,
+    ) -> Result<(String, FileType, CompressionFormat, FileReader), Error> {
         let path = match path.to_str() {
             Some(path) => path,
-            None => return Err(CannotReadFile),
+            None => return Err(Io("Cannot read file".to_string())),
         };
 
-        let file = File::open(path).map_err(|_| CannotReadFile)?;
-
-        let file_type = if path.to_lowercase().ends_with(".bam") {
-            FileType::Bam
-        } else if path.to_lowercase().ends_with(".vcf") {
-            FileType::Vcf
-        } else if path.to_lowercase().ends_with(".bed") {
-            FileType::Bed
-        } else if path.to_lowercase().ends_with(".fastq")
-            || path.to_lowercase().ends_with(".fastq.gz")
-        {
-            FileType::Fastq
-        } else {
-            return Err(UnsupportedFile);
+        let file = File::open(path).map_err(|_| Io("Cannot read file".to_string()))?;
+        let mut reader =
+            BufReader::with_capacity(PREFIX_SIZE, ChecksumReader::with_type(file, checksum_type));
+
+        // Peek at the beginning of the file without consuming it
+        let prefix = reader
+            .fill_buf()
+            .map_err(|_| Io("Cannot read file".to_string()))?;
+        let compression = CompressionFormat::detect(prefix);
+        let content_type = match compression {
+            CompressionFormat::None => detect_file_type(prefix),
+            _ => detect_file_type(&decompressed_prefix(compression, prefix)),
+        };
+
+        let file_type = match content_type.or_else(|| extension_file_type(path)) {
+            Some(file_type) => file_type,
+            None => return Err(Parse("Unsupported file type".to_string())),
+        };
+
+        Ok((path.to_string(), file_type, compression, reader))
+    }
+
+    /// Completes the metadata with checksum and size of the file. The file reader is
+    /// returned once the file content has been dropped.
+    fn complete(
+        path: String,
+        file_type: FileType,
+        checksum_type: &ChecksumType,
+        file_reader: Receiver<FileReader>,
+        metadata: Option<MetadataFile>,
+    ) -> Result<MetadataFile, Error> {
+        // Buffered content was already added to the checksum
+        let (file_checksum, file_size_in_bytes) = file_reader
+            .recv()
+            .map_err(|_| Io("Cannot read file".to_string()))?
+            .into_inner()
+            .finish()
+            .map_err(|_| Io("Cannot read file".to_string()))?;
+
+        let mut metadata_file = match metadata {
+            Some(metadata) => metadata,
+            None => MetadataFile::empty(file_type),
         };
 
-        let file_checksum = match fs::read(path) {
-            Ok(content) => {
-                let mut hasher = Sha256::new();
-                hasher.update(content.as_slice());
-                let hash = hasher.finalize();
-                base16ct::lower::encode_string(&hash)
+        metadata_file.checksum_type = Some(checksum_type.clone());
+        metadata_file.file_checksum = file_checksum;
+        metadata_file.file_size_in_bytes = file_size_in_bytes;
+        metadata_file.file_path = path;
+
+        Ok(metadata_file)
+    }
+
+    /// Reads the BAM header and the first records to find flowcell, lane, read length and
+    /// read order. Flowcell and lane are taken from `PU` fields of read groups like
+    /// `<flowcell>.<lane>` or from Casava like read names if no read group contains them.
+    /// Values not unique to all read groups or records are left empty.
+    fn read_bam(reader: impl Read) -> Result<MetadataFile, Error> {
+        let mut bam_reader = BamReader::new(reader).map_err(read_error)?;
+
+        let mut flowcells = BTreeSet::new();
+        for line in bam_reader.header.lines() {
+            if let Some(fields) = line.strip_prefix("@RG\t") {
+                for platform_unit in fields.split('\t').filter_map(|f| f.strip_prefix("PU:")) {
+                    if let Some(flowcell) = platform_unit_flowcell(platform_unit) {
+                        flowcells.insert(flowcell);
+                    }
+                }
             }
-            Err(_) => {
-                return Err(CannotReadFile);
+        }
+
+        let mut read_name_flowcells = BTreeSet::new();
+        let mut read_leans = BTreeSet::new();
+        let mut read_orders = BTreeSet::new();
+        let mut records = 0;
+        while records < BAM_SAMPLE_RECORDS {
+            let record = match bam_reader.read_record().map_err(read_error)? {
+                Some(record) => record,
+                None => break,
+            };
+            // Skip secondary and supplementary alignments
+            if record.flag() & 0x900 != 0 {
+                continue;
             }
+
+            let read_name = format!("@{} 1:N:0:N", record.read_name);
+            if let Ok(header) = Header::parse(&read_name)
+                && let Some(flowcell_id) = header.flowcell_id()
+            {
+                read_name_flowcells
+                    .insert((flowcell_id.to_string(), header.flowcell_lane().to_string()));
+            }
+            if !record.sequence.is_empty() {
+                read_leans.insert(record.sequence.len() as i64);
+            }
+            read_orders.insert(match record.flag() & 0xc1 {
+                0x41 => Some(ReadOrder::R1),
+                0x81 => Some(ReadOrder::R2),
+                _ => None,
+            });
+            records += 1;
+        }
+
+        if flowcells.is_empty() {
+            flowcells = read_name_flowcells;
+        }
+        let (flowcell_id, lane_id) = if flowcells.len() == 1 {
+            flowcells.into_iter().next().unzip()
+        } else {
+            (None, None)
         };
 
-        if let FileType::Fastq = file_type {
-            match input_reader(Some(PathBuf::from(path)), decompress) {
-                Ok(input_reader) => {
-                    let input_metadata = MetadataFile::read(input_reader)?;
-
-                    Ok(MetadataFile {
-                        file_type,
-                        file_checksum,
-                        checksum_type: Some(ChecksumType::Sha256),
-                        file_size_in_bytes: file.metadata().map_err(|_| CannotReadFile)?.len(),
-                        flowcell_id: input_metadata.flowcell_id,
-                        read_order: input_metadata.read_order,
-                        file_path: path.to_string(),
-                        read_length: input_metadata.read_length,
-                        lane_id: input_metadata.lane_id,
-                    })
+        Ok(MetadataFile {
+            checksum_type: Some(ChecksumType::Sha256),
+            file_checksum: String::new(),
+            file_path: String::new(),
+            file_size_in_bytes: 0,
+            file_type: FileType::Bam,
+            flowcell_id,
+            lane_id,
+            // Reads might be trimmed or clipped, use the length of the longest reads
+            read_length: read_leans.last().copied(),
+            read_order: if read_orders.len() == 1 {
+                read_orders.into_iter().next().flatten()
+            } else {
+                None
+            },
+            reference_build: None,
+        })
+    }
+
+    /// Checks VCF content to contain a `##fileformat` line, a `#CHROM` header line and
+    /// records with the columns of the header line. The reference build is taken from
+    /// `##reference` or `##contig` lines if available.
+    fn read_vcf(reader: impl BufRead) -> Result<MetadataFile, Error> {
+        let mut reference_build = None;
+        let mut columns = None;
+
+        for (idx, line) in reader.lines().enumerate() {
+            let line_number = idx + 1;
+            let line = line.map_err(|err| match err.kind() {
+                ErrorKind::InvalidData => InvalidVcf(line_number, "Invalid UTF-8".into()),
+                _ => Io(format!("Cannot read VCF file at line {}", line_number)),
+            })?;
+
+            if line_number == 1 {
+                if !line.starts_with("##fileformat=VCF") {
+                    return Err(InvalidVcf(
+                        line_number,
+                        "Missing '##fileformat' line".into(),
+                    ));
                 }
-                Err(err) => Err(ReadError(err.to_string())),
+                continue;
+            }
+
+            if let Some(meta) = line.strip_prefix("##") {
+                if columns.is_some() {
+                    return Err(InvalidVcf(
+                        line_number,
+                        "Meta line after header line".into(),
+                    ));
+                }
+                if (meta.starts_with("reference=") || meta.starts_with("contig="))
+                    && reference_build.is_none()
+                {
+                    reference_build = detect_reference_build(meta);
+                }
+                continue;
+            }
+
+            if line.starts_with("#CHROM") {
+                let header = line.split('\t').collect::<Vec<_>>();
+                if header.len() < 8 || header.len() == 9 {
+                    return Err(InvalidVcf(line_number, "Invalid header line".into()));
+                }
+                columns = Some(header.len());
+                continue;
+            }
+
+            let Some(columns) = columns else {
+                return Err(InvalidVcf(
+                    line_number,
+                    "Missing '#CHROM' header line".into(),
+                ));
+            };
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields.len() != columns {
+                return Err(InvalidVcf(
+                    line_number,
+                    format!("Expected {} columns, found {}", columns, fields.len()),
+                ));
+            }
+            if fields[0].is_empty() {
+                return Err(InvalidVcf(line_number, "Missing CHROM".into()));
+            }
+            if fields[1].parse::<u64>().is_err() {
+                return Err(InvalidVcf(
+                    line_number,
+                    format!("Invalid POS '{}'", fields[1]),
+                ));
+            }
+            if fields[3].is_empty()
+                || !fields[3]
+                    .chars()
+                    .all(|c| matches!(c.to_ascii_uppercase(), 'A' | 'C' | 'G' | 'T' | 'N'))
+            {
+                return Err(InvalidVcf(
+                    line_number,
+                    format!("Invalid REF '{}'", fields[3]),
+                ));
+            }
+            if fields[4].is_empty() {
+                return Err(InvalidVcf(line_number, "Missing ALT".into()));
             }
-        } else {
-            Ok(MetadataFile {
-                file_type,
-                file_checksum,
-                checksum_type: Some(ChecksumType::Sha256),
-                file_size_in_bytes: file.metadata().map_err(|_| CannotReadFile)?.len(),
-                flowcell_id: None,
-                read_order: None,
-                file_path: path.to_string(),
-                read_length: None,
-                lane_id: None,
-            })
         }
+
+        if columns.is_none() {
+            return Err(InvalidVcf(0, "Missing '#CHROM' header line".into()));
+        }
+
+        let mut metadata = MetadataFile::empty(FileType::Vcf);
+        metadata.reference_build = reference_build;
+        Ok(metadata)
     }
 
-    fn read(mut reader: impl BufRead) -> Result<MetadataFile, MetadataError> {
-        let mut buf = String::new();
+    /// Checks BED content to contain regions with valid start and end positions, sorted
+    /// by start position and grouped by chromosome. The reference build is taken from
+    /// `db` of track lines if available.
+    fn read_bed(reader: impl BufRead) -> Result<MetadataFile, Error> {
+        let mut reference_build = None;
+        let mut chroms = HashSet::new();
+        let mut last: Option<(String, u64)> = None;
 
-        let mut headers = vec![];
-        let mut read_lens = vec![];
-        let mut quality_lens = vec![];
+        for (idx, line) in reader.lines().enumerate() {
+            let line_number = idx + 1;
+            let line = line.map_err(|err| match err.kind() {
+                ErrorKind::InvalidData => InvalidBed(line_number, "Invalid UTF-8".into()),
+                _ => Io(format!("Cannot read BED file at line {}", line_number)),
+            })?;
 
-        let mut line = 1;
-        while let Ok(n) = reader.read_line(&mut buf) {
-            if n == 0 {
-                break;
+            if line.starts_with("track") {
+                if reference_build.is_none() {
+                    reference_build = line
+                        .split_whitespace()
+                        .find_map(|field| field.strip_prefix("db="))
+                        .and_then(detect_reference_build);
+                }
+                continue;
             }
 
-            if buf.starts_with("@") {
-                if let Ok(header) = buf.parse::<Header>() {
-                    headers.push(header)
-                } else {
-                    return Err(ReadError(format!("Invalid header at line {}", line)));
+            if line.is_empty() || line.starts_with('#') || line.starts_with("browser") {
+                continue;
+            }
+
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields.len() < 3 || fields[0].is_empty() {
+                return Err(InvalidBed(
+                    line_number,
+                    "Expected chrom, start and end".into(),
+                ));
+            }
+
+            let chrom = fields[0];
+            let start = fields[1]
+                .parse::<u64>()
+                .map_err(|_| InvalidBed(line_number, format!("Invalid start '{}'", fields[1])))?;
+            let end = fields[2]
+                .parse::<u64>()
+                .map_err(|_| InvalidBed(line_number, format!("Invalid end '{}'", fields[2])))?;
+            if start >= end {
+                return Err(InvalidBed(
+                    line_number,
+                    format!("Start {} is not before end {}", start, end),
+                ));
+            }
+
+            match &last {
+                Some((last_chrom, last_start)) if last_chrom == chrom => {
+                    if start < *last_start {
+                        return Err(InvalidBed(line_number, "Regions are not sorted".into()));
+                    }
                 }
-            } else if buf.starts_with("+") {
-                // ignore optional description
-            } else if line % 4 == 0 {
-                // check if quality values differs from sequence values
-                if Some(&buf.trim().len()) != read_lens.last() {
-                    return Err(ReadError(format!(
-                        "Invalid quality string length at line {}",
-                        line
-                    )));
+                _ => {
+                    if !chroms.insert(chrom.to_string()) {
+                        return Err(InvalidBed(
+                            line_number,
+                            format!("Regions of '{}' are not grouped", chrom),
+                        ));
+                    }
                 }
-                quality_lens.push(buf.trim().len());
-            } else if line % 4 == 2 {
-                read_lens.push(buf.trim().len());
             }
+            last = Some((chrom.to_string(), start));
+        }
 
-            line += 1;
-            buf.clear();
+        if last.is_none() {
+            return Err(InvalidBed(0, "No regions found".into()));
         }
 
-        if line == 1 {
-            return Err(ReadError("No valid input".to_string()));
+        let mut metadata = MetadataFile::empty(FileType::Bed);
+        metadata.reference_build = reference_build;
+        Ok(metadata)
+    }
+
+    /// Returns metadata without any values of file content
+    fn empty(file_type: FileType) -> MetadataFile {
+        MetadataFile {
+            checksum_type: Some(ChecksumType::Sha256),
+            file_checksum: String::new(),
+            file_path: String::new(),
+            file_size_in_bytes: 0,
+            file_type,
+            flowcell_id: None,
+            lane_id: None,
+            read_length: None,
+            read_order: None,
+            reference_build: None,
         }
+    }
 
-        if line % 4 != 1 {
-            return Err(ReadError(
-                "File contains invalid or incomplete sequences".to_string(),
-            ));
+    /// Returns a description of each value differing from actual file metadata. Actual
+    /// metadata is expected to use the checksum type of this metadata.
+    pub fn mismatches(&self, actual: &MetadataFile) -> Vec<String> {
+        [mismatch("File type", &self.file_type, &actual.file_type)]
+            .into_iter()
+            .flatten()
+            .chain(self.file_mismatches(&actual.file_checksum, actual.file_size_in_bytes))
+            .chain(
+                [
+                    mismatch("Flowcell ID", &self.flowcell_id, &actual.flowcell_id),
+                    mismatch("Lane ID", &self.lane_id, &actual.lane_id),
+                    mismatch("Read length", &self.read_length, &actual.read_length),
+                    mismatch("Read order", &self.read_order, &actual.read_order),
+                ]
+                .into_iter()
+                .flatten(),
+            )
+            .collect()
+    }
+
+    /// Returns a description of checksum and size differing from actual values of the file,
+    /// which are available even if the file content is invalid
+    pub fn file_mismatches(&self, file_checksum: &str, file_size_in_bytes: u64) -> Vec<String> {
+        [
+            mismatch("Checksum", &self.file_checksum.as_str(), &file_checksum),
+            mismatch("File size", &self.file_size_in_bytes, &file_size_in_bytes),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// Returns a description of the value if it differs from actual value
+fn mismatch<T: Serialize + PartialEq>(name: &str, expected: &T, actual: &T) -> Option<String> {
+    fn json<T: Serialize>(value: &T) -> String {
+        serde_json::to_string(value).unwrap_or_default()
+    }
+
+    if expected == actual {
+        None
+    } else {
+        Some(format!(
+            "{} differs: expected {}, found {}",
+            name,
+            json(expected),
+            json(actual)
+        ))
+    }
+}
+
+/// Builder reading metadata of files using the same options, see `MetadataFile::builder()`
+#[derive(Clone, Debug)]
+pub struct MetadataFileBuilder {
+    long_read: bool,
+    checksum_type: ChecksumType,
+    threads: usize,
+}
+
+impl Default for MetadataFileBuilder {
+    fn default() -> Self {
+        MetadataFileBuilder {
+            long_read: false,
+            checksum_type: ChecksumType::Sha256,
+            threads: 1,
         }
+    }
+}
+
+impl MetadataFileBuilder {
+    /// Selects long-read mode for FASTQ files not containing Nanopore or PacBio headers
+    pub fn long_read(mut self, long_read: bool) -> Self {
+        self.long_read = long_read;
+        self
+    }
 
-        // Flowcell IDs
+    /// Sets the type of checksum calculated for each file
+    pub fn checksum_type(mut self, checksum_type: ChecksumType) -> Self {
+        self.checksum_type = checksum_type;
+        self
+    }
 
-        let flowcell_ids = headers
-            .iter()
-            .filter_map(|header| header.flowcell_id())
-            .sorted()
-            .chunk_by(|value| value.clone())
-            .into_iter()
-            .map(|g| g.0)
-            .collect::<Vec<String>>();
+    /// Sets the number of threads, file content is decompressed on a separate thread if
+    /// more than one thread is used
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
 
-        // Flowcell Lanes
+    /// Reads metadata of given file, see `MetadataFile::read_file()`
+    pub fn read_file(&self, path: impl Into<PathBuf>) -> Result<MetadataFile, Error> {
+        MetadataFile::read_file(
+            path.into(),
+            self.long_read,
+            &self.checksum_type,
+            self.threads,
+        )
+    }
 
-        let flowcell_lanes = headers
-            .iter()
-            .map(|header| header.flowcell_lane())
-            .sorted()
-            .chunk_by(|value| value.to_string())
-            .into_iter()
-            .map(|g| g.0)
-            .collect::<Vec<String>>();
+    /// Reads metadata of R1 and R2 files of paired reads, see
+    /// `MetadataFile::read_paired_files()`. Long-read mode is not used for paired reads.
+    pub fn read_paired_files(
+        &self,
+        path_1: impl Into<PathBuf>,
+        path_2: impl Into<PathBuf>,
+    ) -> Result<(MetadataFile, MetadataFile), Error> {
+        MetadataFile::read_paired_files(
+            path_1.into(),
+            path_2.into(),
+            &self.checksum_type,
+            self.threads,
+        )
+    }
+}
 
-        // Read Orders
+/// Reader of file content also calculating checksum and size of the file
+type FileReader = BufReader<ChecksumReader<File>>;
 
-        let read_orders = headers
-            .iter()
-            .map(|header| match header.pair_member() {
-                Pair::PairedEnd => "R1",
-                Pair::MatePair => "R2",
-            })
-            .sorted()
-            .chunk_by(|value| value.to_string())
-            .into_iter()
-            .map(|g| g.0)
-            .collect::<Vec<String>>();
+/// Number of bytes at the beginning of a file used to detect the file type
+const PREFIX_SIZE: usize = 64 * 1024;
+
+/// Returns a reader decoding file content and a receiver of the file reader, which is sent
+/// back once the content is dropped. Content is decoded on a separate thread if more than
+/// one thread is used.
+fn file_content(
+    reader: FileReader,
+    compression: CompressionFormat,
+    threads: usize,
+) -> Result<(Box<dyn BufRead>, Receiver<FileReader>), Error> {
+    let (sender, receiver) = channel();
+    let reader = ReturningReader {
+        reader: Some(reader),
+        sender,
+    };
+
+    let content: Box<dyn BufRead> = if threads > 1 {
+        Box::new(ThreadedReader::new(move || compression.decoder(reader)))
+    } else {
+        compression
+            .decoder(reader)
+            .map_err(|_| Io("Cannot read file".to_string()))?
+    };
+    Ok((content, receiver))
+}
+
+/// File reader sending itself back when dropped, so the checksum of the file can be
+/// completed after the content was read by a decoder, possibly on another thread.
+struct ReturningReader {
+    reader: Option<FileReader>,
+    sender: Sender<FileReader>,
+}
 
-        // Read Lengths
+impl Read for ReturningReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.as_mut().expect("Reader is present").read(buf)
+    }
+}
+
+impl BufRead for ReturningReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.as_mut().expect("Reader is present").fill_buf()
+    }
 
-        let read_leans = read_lens
+    fn consume(&mut self, amt: usize) {
+        self.reader
+            .as_mut()
+            .expect("Reader is present")
+            .consume(amt)
+    }
+}
+
+impl Drop for ReturningReader {
+    fn drop(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            // Receiver is dropped if reading the file content has failed
+            let _ = self.sender.send(reader);
+        }
+    }
+}
+
+/// Returns as much decompressed content as available in given compressed prefix of a file
+fn decompressed_prefix(compression: CompressionFormat, prefix: &[u8]) -> Vec<u8> {
+    let mut decoder = match compression.decoder(prefix) {
+        Ok(decoder) => decoder,
+        Err(_) => return vec![],
+    };
+    let mut result = vec![];
+    let mut buf = [0; 4096];
+    while result.len() < PREFIX_SIZE {
+        match decoder.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => result.extend_from_slice(&buf[..n]),
+        }
+    }
+    result
+}
+
+/// Returns the file type detected from the (decompressed) beginning of file content
+fn detect_file_type(content: &[u8]) -> Option<FileType> {
+    if content.starts_with(b"BAM\x01") {
+        return Some(FileType::Bam);
+    }
+    if content.starts_with(b"##fileformat=VCF") {
+        return Some(FileType::Vcf);
+    }
+
+    let lines = content.split(|b| *b == b'\n').collect::<Vec<_>>();
+
+    if lines.len() > 3 && lines[0].starts_with(b"@") && lines[2].starts_with(b"+") {
+        return Some(FileType::Fastq);
+    }
+
+    // First region of a BED file: chrom, start and end separated by tabs
+    let region = lines.iter().find(|line| {
+        !line.is_empty()
+            && !line.starts_with(b"#")
+            && !line.starts_with(b"track")
+            && !line.starts_with(b"browser")
+    })?;
+    let fields = region.split(|b| *b == b'\t').collect::<Vec<_>>();
+    if fields.len() >= 3
+        && !fields[0].is_empty()
+        && [fields[1], fields[2]]
             .iter()
-            .sorted()
-            .chunk_by(|value| value.to_string())
-            .into_iter()
-            .map(|g| g.0.parse::<i64>().unwrap())
-            .collect::<Vec<i64>>();
+            .all(|field| !field.is_empty() && field.iter().all(u8::is_ascii_digit))
+    {
+        return Some(FileType::Bed);
+    }
+
+    None
+}
+
+/// Returns the file type indicated by the file extension, ignoring compression extensions
+fn extension_file_type(path: &str) -> Option<FileType> {
+    let path = path.to_lowercase();
+    let path = path
+        .strip_suffix(".gz")
+        .or_else(|| path.strip_suffix(".bgz"))
+        .unwrap_or(&path);
+
+    match path.rsplit_once('.')?.1 {
+        "bam" => Some(FileType::Bam),
+        "vcf" => Some(FileType::Vcf),
+        "bed" => Some(FileType::Bed),
+        "fastq" | "fq" => Some(FileType::Fastq),
+        _ => None,
+    }
+}
+
+/// Returns the reference build named in a VCF meta line or BED track line
+fn detect_reference_build(value: &str) -> Option<String> {
+    let value = value.to_lowercase();
+    if value.contains("grch38") || value.contains("hg38") || value.contains("length=248956422") {
+        Some("GRCh38".to_string())
+    } else if value.contains("grch37")
+        || value.contains("hg19")
+        || value.contains("hs37d5")
+        || value.contains("length=249250621")
+    {
+        Some("GRCh37".to_string())
+    } else {
+        None
+    }
+}
+
+/// Number of primary alignments of BAM files inspected for metadata
+const BAM_SAMPLE_RECORDS: usize = 10_000;
+
+/// Returns read error of invalid file content with given message
+fn content_error(message: String) -> Error {
+    read_error(Parse(message))
+}
+
+/// Returns paired files mismatch as validation failure
+fn pair_mismatch(message: String) -> Error {
+    Validation(format!("Paired files do not match: {}", message))
+}
+
+/// Returns parse errors of file content as read error, keeping I/O errors
+fn read_error(err: Error) -> Error {
+    match err {
+        Parse(err) => Parse(format!("Error reading file: {}", err)),
+        err => err,
+    }
+}
+
+/// Returns flowcell and lane of a platform unit like `<flowcell>.<lane>[.<barcode>]`
+fn platform_unit_flowcell(platform_unit: &str) -> Option<(String, String)> {
+    let mut parts = platform_unit.split(['.', ':']);
+    let flowcell = parts.next().filter(|flowcell| !flowcell.is_empty())?;
+    let lane = parts.next()?.parse::<u32>().ok()?;
+    Some((flowcell.to_string(), lane.to_string()))
+}
+
+/// Distinct values of FASTQ records used for file metadata.
+///
+/// Only distinct values and the total read length are kept to not grow with file size.
+/// Long reads of Nanopore or PacBio have different read lengths and might not contain a
+/// lane or read order. Long-read mode is used if selected or if the first record contains
+/// a long-read header. If detected, headers of all records must match the detected mode.
+#[derive(Default)]
+struct FastqMetadata {
+    long_read: Option<bool>,
+    long_read_selected: bool,
+    flowcell_ids: BTreeSet<String>,
+    flowcell_lanes: BTreeSet<u32>,
+    read_orders: BTreeSet<&'static str>,
+    read_leans: BTreeSet<i64>,
+    total_read_length: u64,
+    records: usize,
+}
+
+impl FastqMetadata {
+    fn new(long_read: bool) -> Self {
+        FastqMetadata {
+            long_read: long_read.then_some(true),
+            long_read_selected: long_read,
+            ..FastqMetadata::default()
+        }
+    }
+
+    /// Adds values of the record and returns its parsed header if it is not a long-read
+    /// header. Distinct values are copied only, so records are added without allocating.
+    fn add<'a>(&mut self, record: &RecordRef<'a>) -> Result<Option<Header<'a>>, Error> {
+        let line = record.line();
+        let invalid_header =
+            |err: Error| content_error(format!("Invalid header at line {line}: {err}"));
+
+        let header = record.header();
+        let long_read = *self
+            .long_read
+            .get_or_insert_with(|| header.is_err() && record.long_read_header().is_ok());
+
+        // Selected long-read mode accepts all headers, a detected mode only matching headers
+        let conflicting = match header {
+            Ok(_) => long_read,
+            Err(_) => !long_read && record.long_read_header().is_ok(),
+        };
+        if conflicting && !self.long_read_selected {
+            return Err(content_error(format!(
+                "Conflicting header at line {line}: Header does not match {} header of first record",
+                if long_read { "long-read" } else { "short-read" }
+            )));
+        }
+
+        let header = match header {
+            Ok(header) => {
+                if let Some(flowcell_id) = header.flowcell_id()
+                    && !self.flowcell_ids.contains(flowcell_id)
+                {
+                    self.flowcell_ids.insert(flowcell_id.to_string());
+                }
+                self.flowcell_lanes.insert(header.flowcell_lane());
+                if !long_read {
+                    self.read_orders.insert(match header.pair_member() {
+                        Pair::PairedEnd => "R1",
+                        Pair::MatePair => "R2",
+                    });
+                }
+                Some(header)
+            }
+            Err(_) if long_read => {
+                let header = record.long_read_header().map_err(invalid_header)?;
+                if let Some(flowcell_id) = header.flowcell_id()
+                    && !self.flowcell_ids.contains(flowcell_id)
+                {
+                    self.flowcell_ids.insert(flowcell_id.to_string());
+                }
+                None
+            }
+            Err(err) => return Err(invalid_header(err)),
+        };
+
+        // check if quality values differs from sequence values
+        if record.quality().len() != record.sequence().len() {
+            return Err(content_error(format!(
+                "Invalid quality string length at line {}",
+                line + 3
+            )));
+        }
+
+        if !long_read {
+            self.read_leans.insert(record.sequence().len() as i64);
+        }
+        self.total_read_length += record.sequence().len() as u64;
+        self.records += 1;
+
+        Ok(header)
+    }
+
+    fn finish(self) -> Result<MetadataFile, Error> {
+        let FastqMetadata {
+            long_read,
+            long_read_selected: _,
+            flowcell_ids,
+            flowcell_lanes,
+            read_orders,
+            read_leans,
+            total_read_length,
+            records,
+        } = self;
+
+        if records == 0 {
+            return Err(content_error("No valid input".to_string()));
+        }
+
+        if long_read == Some(true) {
+            return Ok(MetadataFile {
+                flowcell_id: match flowcell_ids.len() {
+                    0 => None,
+                    1 => flowcell_ids.into_iter().next(),
+                    _ => return Err(content_error("Cannot find single flowcell id".to_string())),
+                },
+                lane_id: match flowcell_lanes.len() {
+                    0 => None,
+                    1 => flowcell_lanes.first().map(u32::to_string),
+                    _ => return Err(content_error("Cannot find single lane id".to_string())),
+                },
+                read_length: Some((total_read_length as f64 / records as f64).round() as i64),
+                ..MetadataFile::empty(FileType::Fastq)
+            });
+        }
 
         Ok(MetadataFile {
             checksum_type: Some(ChecksumType::Sha256),
@@ -278,30 +1017,453 @@ impl MetadataFile {
             flowcell_id: if flowcell_ids.len() == 1 {
                 Some(flowcell_ids.into_iter().nth(0).unwrap())
             } else {
-                return Err(ReadError("Cannot find single flowcell id".to_string()));
+                return Err(content_error("Cannot find single flowcell id".to_string()));
             },
             lane_id: if flowcell_lanes.len() == 1 {
-                Some(flowcell_lanes.into_iter().nth(0).unwrap())
+                flowcell_lanes.first().map(u32::to_string)
             } else {
-                return Err(ReadError("Cannot find single lane id".to_string()));
+                return Err(content_error("Cannot find single lane id".to_string()));
             },
             read_length: if read_leans.len() == 1 {
                 Some(read_leans.into_iter().nth(0).unwrap())
             } else {
-                return Err(ReadError("Cannot find single lane id".to_string()));
+                return Err(content_error(
+                    "Cannot find single read length, use long-read mode for long reads".to_string(),
+                ));
             },
             read_order: if read_orders.len() == 1 {
                 match read_orders.into_iter().nth(0) {
                     None => None,
-                    Some(value) => match value.as_str() {
+                    Some(value) => match value {
                         "R1" => Some(ReadOrder::R1),
                         "R2" => Some(ReadOrder::R2),
                         _ => None,
                     },
                 }
             } else {
-                return Err(ReadError("Cannot find single lane id".to_string()));
+                return Err(content_error("Cannot find single read order".to_string()));
             },
+            reference_build: None,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::compression::CompressionFormat;
+    use crate::error::Error::{InvalidBed, InvalidVcf, Parse, Validation};
+    use crate::fastq::RecordReader;
+    use crate::metadata_file::{
+        ChecksumType, FastqMetadata, FileType, MetadataFile, ReadOrder, decompressed_prefix,
+        detect_file_type, extension_file_type, platform_unit_flowcell,
+    };
+    use crate::test_util::{R1_RECORD_1, R1_RECORD_2, R2_RECORD_1, R2_RECORD_2, bam, temp_dir};
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    #[test]
+    fn should_validate_vcf_and_detect_reference_build() {
+        let given = "##fileformat=VCFv4.2\n\
+            ##reference=file:///refs/GRCh38.fa\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+            chr1\t5\t.\tA\tG\t50\tPASS\t.\n";
+
+        let actual = MetadataFile::read_vcf(given.as_bytes()).unwrap();
+
+        assert_eq!(actual.reference_build, Some("GRCh38".to_string()));
+    }
+
+    #[test]
+    fn should_return_error_for_invalid_vcf() {
+        let given = "##fileformat=VCFv4.2\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+            chr1\tabc\t.\tA\tG\t50\tPASS\t.\n";
+
+        let actual = MetadataFile::read_vcf(given.as_bytes());
+
+        assert!(matches!(actual, Err(InvalidVcf(3, _))));
+        assert!(matches!(
+            MetadataFile::read_vcf("#CHROM\tPOS\n".as_bytes()),
+            Err(InvalidVcf(1, _))
+        ));
+    }
+
+    #[test]
+    fn should_validate_bed() {
+        let given = "track name=regions db=hg19\nchr1\t10\t20\nchr1\t15\t30\nchr2\t0\t5\n";
+
+        let actual = MetadataFile::read_bed(given.as_bytes()).unwrap();
+
+        assert_eq!(actual.reference_build, Some("GRCh37".to_string()));
+    }
+
+    #[test]
+    fn should_return_error_for_invalid_bed() {
+        let unsorted = "chr1\t10\t20\nchr1\t5\t30\n";
+        let invalid_end = "chr1\t10\t10\n";
+        let not_grouped = "chr1\t10\t20\nchr2\t10\t20\nchr1\t30\t40\n";
+
+        assert!(matches!(
+            MetadataFile::read_bed(unsorted.as_bytes()),
+            Err(InvalidBed(2, _))
+        ));
+        assert!(matches!(
+            MetadataFile::read_bed(invalid_end.as_bytes()),
+            Err(InvalidBed(1, _))
+        ));
+        assert!(matches!(
+            MetadataFile::read_bed(not_grouped.as_bytes()),
+            Err(InvalidBed(3, _))
+        ));
+    }
+
+    #[test]
+    fn should_detect_file_type_from_content() {
+        let fastq = "@TEST:1:FC:1:1:1:1 1:N:0:1\nACGT\n+\nIIII\n";
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(fastq.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert_eq!(
+            detect_file_type(&decompressed_prefix(CompressionFormat::Gzip, &compressed)),
+            Some(FileType::Fastq)
+        );
+        assert_eq!(
+            detect_file_type(b"##fileformat=VCFv4.2\n"),
+            Some(FileType::Vcf)
+        );
+        assert_eq!(detect_file_type(b"BAM\x01\0\0\0\0"), Some(FileType::Bam));
+        assert_eq!(
+            detect_file_type(b"track name=x\nchr1\t10\t20\n"),
+            Some(FileType::Bed)
+        );
+        assert_eq!(detect_file_type(b"some text\n"), None);
+    }
+
+    #[test]
+    fn should_detect_file_type_from_extension() {
+        assert_eq!(extension_file_type("reads.fq.gz"), Some(FileType::Fastq));
+        assert_eq!(extension_file_type("calls.VCF.bgz"), Some(FileType::Vcf));
+        assert_eq!(extension_file_type("regions.bed"), Some(FileType::Bed));
+        assert_eq!(extension_file_type("archive.tar.gz"), None);
+    }
+
+    #[test]
+    fn should_use_long_read_mode_for_nanopore_reads() {
+        let given = "@0c9d1a47-2b9e-4f3a-9c41-7d7f1e2a3b4c runid=abc read=1 flow_cell_id=FAL12345\n\
+            ACGTACGTAC\n+\nIIIIIIIIII\n\
+            @1d9d1a47-2b9e-4f3a-9c41-7d7f1e2a3b4c runid=abc read=2 flow_cell_id=FAL12345\n\
+            ACGTA\n+\nIIIII\n";
+
+        let mut fastq_metadata = FastqMetadata::new(false);
+        let mut records = RecordReader::new(given.as_bytes());
+        while let Some(record) = records.read_record() {
+            fastq_metadata.add(&record.unwrap()).unwrap();
+        }
+        let actual = fastq_metadata.finish().unwrap();
+
+        assert_eq!(actual.flowcell_id, Some("FAL12345".to_string()));
+        assert_eq!(actual.lane_id, None);
+        assert_eq!(actual.read_length, Some(8));
+        assert_eq!(actual.read_order, None);
+    }
+
+    #[test]
+    fn should_return_error_for_conflicting_short_and_long_read_headers() {
+        let long_read = "@0c9d1a47-2b9e-4f3a-9c41-7d7f1e2a3b4c runid=abc read=1 flow_cell_id=FAL12345\n\
+            ACGTACGTAC\n+\nIIIIIIIIII\n";
+
+        for (given, line) in [
+            ([long_read, R1_RECORD_1].concat(), 5),
+            ([R1_RECORD_1, long_read].concat(), 5),
+        ] {
+            let mut fastq_metadata = FastqMetadata::new(false);
+            let mut records = RecordReader::new(given.as_bytes());
+            let mut actual = Ok(());
+            while let Some(record) = records.read_record()
+                && actual.is_ok()
+            {
+                actual = fastq_metadata.add(&record.unwrap()).map(|_| ());
+            }
+
+            assert!(matches!(
+                actual,
+                Err(Parse(err)) if err.contains(&format!("Conflicting header at line {line}"))
+            ));
+        }
+
+        let mut fastq_metadata = FastqMetadata::new(true);
+        let given = [&long_read.replace("FAL12345", "FC706VJ"), R1_RECORD_1].concat();
+        let mut records = RecordReader::new(given.as_bytes());
+        while let Some(record) = records.read_record() {
+            fastq_metadata.add(&record.unwrap()).unwrap();
+        }
+        assert_eq!(fastq_metadata.finish().unwrap().read_length, Some(7));
+    }
+
+    #[test]
+    fn should_return_mismatches_of_file_metadata() {
+        let expected = MetadataFile {
+            checksum_type: Some(ChecksumType::Sha256),
+            file_checksum: "abc".to_string(),
+            file_path: "donor/lab_data/file.fastq".to_string(),
+            file_size_in_bytes: 100,
+            file_type: FileType::Fastq,
+            flowcell_id: Some("FC706VJ".to_string()),
+            lane_id: Some("2".to_string()),
+            read_length: Some(100),
+            read_order: Some(ReadOrder::R1),
+            reference_build: None,
+        };
+        let actual = MetadataFile {
+            file_checksum: "def".to_string(),
+            lane_id: Some("3".to_string()),
+            read_order: Some(ReadOrder::R2),
+            ..expected.clone()
+        };
+
+        assert_eq!(expected.mismatches(&expected), Vec::<String>::new());
+        assert_eq!(
+            expected.mismatches(&actual),
+            vec![
+                "Checksum differs: expected \"abc\", found \"def\"",
+                "Lane ID differs: expected \"2\", found \"3\"",
+                "Read order differs: expected \"R1\", found \"R2\"",
+            ]
+        );
+        assert_eq!(
+            expected.file_mismatches("abc", 99),
+            vec!["File size differs: expected 100, found 99"]
+        );
+    }
+
+    #[test]
+    fn should_read_paired_files_of_mates() {
+        let r1 = [R1_RECORD_1, R1_RECORD_2].concat();
+        let r2 = [R2_RECORD_1, R2_RECORD_2].concat();
+        let dir = temp_dir(&[("sample_R1.fastq", &r1), ("sample_R2.fastq", &r2)]);
+        let (path_1, path_2) = (
+            dir.path().join("sample_R1.fastq"),
+            dir.path().join("sample_R2.fastq"),
+        );
+
+        let (actual_1, actual_2) =
+            MetadataFile::read_paired_files(path_1, path_2, &ChecksumType::Sha256, 1).unwrap();
+
+        assert_eq!(actual_1.read_order, Some(ReadOrder::R1));
+        assert_eq!(actual_2.read_order, Some(ReadOrder::R2));
+        assert_eq!(actual_1.flowcell_id, Some("FC706VJ".to_string()));
+        assert_eq!(actual_2.flowcell_id, Some("FC706VJ".to_string()));
+        assert_eq!(actual_1.read_length, Some(4));
+        assert_eq!(actual_1.file_size_in_bytes, r1.len() as u64);
+        assert_eq!(actual_2.file_size_in_bytes, r2.len() as u64);
+    }
+
+    #[test]
+    fn should_return_error_if_record_counts_of_paired_files_differ() {
+        let r1 = [R1_RECORD_1, R1_RECORD_2].concat();
+        let r2 = [R2_RECORD_1, R2_RECORD_2].concat();
+        let dir = temp_dir(&[
+            ("sample_R1.fastq", r1.as_str()),
+            ("sample_R2.fastq", R2_RECORD_1),
+        ]);
+        let (path_1, path_2) = (
+            dir.path().join("sample_R1.fastq"),
+            dir.path().join("sample_R2.fastq"),
+        );
+
+        let actual = MetadataFile::read_paired_files(path_1, path_2, &ChecksumType::Sha256, 1);
+
+        assert_eq!(
+            actual.err(),
+            Some(Validation(
+                "Paired files do not match: R2 file ends before record 2 of R1 file".to_string()
+            ))
+        );
+
+        let dir = temp_dir(&[("sample_R1.fastq", R1_RECORD_1), ("sample_R2.fastq", &r2)]);
+        let (path_1, path_2) = (
+            dir.path().join("sample_R1.fastq"),
+            dir.path().join("sample_R2.fastq"),
+        );
+
+        let actual = MetadataFile::read_paired_files(path_1, path_2, &ChecksumType::Sha256, 1);
+
+        assert_eq!(
+            actual.err(),
+            Some(Validation(
+                "Paired files do not match: R1 file ends before record 2 of R2 file".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn should_return_first_mismatching_record_of_paired_files() {
+        let r1 = [R1_RECORD_1, R1_RECORD_2].concat();
+        let r2 = [R2_RECORD_1, &R2_RECORD_2.replace("197394", "197395")].concat();
+        let dir = temp_dir(&[("sample_R1.fastq", &r1), ("sample_R2.fastq", &r2)]);
+        let (path_1, path_2) = (
+            dir.path().join("sample_R1.fastq"),
+            dir.path().join("sample_R2.fastq"),
+        );
+
+        let actual = MetadataFile::read_paired_files(path_1, path_2, &ChecksumType::Sha256, 1);
+
+        assert_eq!(
+            actual.err(),
+            Some(Validation(
+                "Paired files do not match: Record 2 at line 5 does not match: \
+                '@EAS139:136:FC706VJ:2:2104:15343:197394 1:Y:18:ATCACG' and \
+                '@EAS139:136:FC706VJ:2:2104:15343:197395 2:Y:18:ATCACG'"
+                    .to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn should_return_error_if_read_orders_of_paired_files_are_swapped() {
+        let dir = temp_dir(&[
+            ("sample_R1.fastq", R2_RECORD_1),
+            ("sample_R2.fastq", R1_RECORD_1),
+        ]);
+        let (path_1, path_2) = (
+            dir.path().join("sample_R1.fastq"),
+            dir.path().join("sample_R2.fastq"),
+        );
+
+        let actual = MetadataFile::read_paired_files(path_1, path_2, &ChecksumType::Sha256, 1);
+
+        assert!(matches!(
+            actual,
+            Err(Validation(err)) if err.starts_with("Paired files do not match: Record 1 at line 1")
+        ));
+    }
+
+    #[test]
+    fn should_parse_flowcell_and_lane_of_platform_unit() {
+        assert_eq!(
+            platform_unit_flowcell("FC706VJ.2"),
+            Some(("FC706VJ".to_string(), "2".to_string()))
+        );
+        assert_eq!(
+            platform_unit_flowcell("FC706VJ:2:ATCACG"),
+            Some(("FC706VJ".to_string(), "2".to_string()))
+        );
+        assert_eq!(
+            platform_unit_flowcell("FC706VJ.02.ATCACG"),
+            Some(("FC706VJ".to_string(), "2".to_string()))
+        );
+        assert_eq!(platform_unit_flowcell("FC706VJ"), None);
+        assert_eq!(platform_unit_flowcell("FC706VJ.A"), None);
+        assert_eq!(platform_unit_flowcell(".2"), None);
+    }
+
+    #[test]
+    fn should_read_flowcell_and_lane_of_bam_platform_unit() {
+        let given = bam(
+            "@HD\tVN:1.6\n@RG\tID:rg1\tPU:FC706VJ.2.ATCACG\tSM:sample\n",
+            &[("EAS139:136:FCX:3:2104:15343:197393", 0x41, "GATT")],
+        );
+
+        let actual = MetadataFile::read_bam(given.as_slice()).unwrap();
+
+        assert_eq!(actual.file_type, FileType::Bam);
+        assert_eq!(actual.flowcell_id, Some("FC706VJ".to_string()));
+        assert_eq!(actual.lane_id, Some("2".to_string()));
+        assert_eq!(actual.read_length, Some(4));
+        assert_eq!(actual.read_order, Some(ReadOrder::R1));
+    }
+
+    #[test]
+    fn should_read_flowcell_and_lane_of_bam_read_names_without_platform_unit() {
+        let given = bam(
+            "@HD\tVN:1.6\n@RG\tID:rg1\tPU:unknown\tSM:sample\n",
+            &[
+                ("EAS139:136:FC706VJ:3:2104:15343:197393", 0x81, "GATTACA"),
+                ("EAS139:136:FC706VJ:3:2104:15343:197394", 0x81, "GATT"),
+            ],
+        );
+
+        let actual = MetadataFile::read_bam(given.as_slice()).unwrap();
+
+        assert_eq!(actual.flowcell_id, Some("FC706VJ".to_string()));
+        assert_eq!(actual.lane_id, Some("3".to_string()));
+        assert_eq!(actual.read_length, Some(7));
+        assert_eq!(actual.read_order, Some(ReadOrder::R2));
+    }
+
+    #[test]
+    fn should_skip_secondary_and_supplementary_bam_alignments() {
+        let given = bam(
+            "@HD\tVN:1.6\n",
+            &[
+                ("EAS139:136:FC706VJ:2:2104:15343:197393", 0x41, "GATT"),
+                (
+                    "EAS139:136:FCSECOND:4:2104:15343:197393",
+                    0x141,
+                    "GATTACAGATTACA",
+                ),
+                (
+                    "EAS139:136:FCSUPPL:5:2104:15343:197393",
+                    0x881,
+                    "GATTACAGATTACA",
+                ),
+            ],
+        );
+
+        let actual = MetadataFile::read_bam(given.as_slice()).unwrap();
+
+        assert_eq!(actual.flowcell_id, Some("FC706VJ".to_string()));
+        assert_eq!(actual.lane_id, Some("2".to_string()));
+        assert_eq!(actual.read_length, Some(4));
+        assert_eq!(actual.read_order, Some(ReadOrder::R1));
+    }
+
+    #[test]
+    fn should_read_same_metadata_using_multiple_threads() {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder
+            .write_all([R1_RECORD_1, R1_RECORD_2].concat().as_bytes())
+            .unwrap();
+        let dir = temp_dir(&[("sample_R1.fastq.gz", encoder.finish().unwrap())]);
+        let path = dir.path().join("sample_R1.fastq.gz");
+
+        let expected =
+            MetadataFile::read_file(path.clone(), false, &ChecksumType::Sha256, 1).unwrap();
+        let actual = MetadataFile::read_file(path, false, &ChecksumType::Sha256, 4).unwrap();
+
+        assert_eq!(actual.file_checksum, expected.file_checksum);
+        assert_eq!(actual.file_size_in_bytes, expected.file_size_in_bytes);
+        assert_eq!(actual.flowcell_id, Some("FC706VJ".to_string()));
+        assert_eq!(actual.lane_id, Some("2".to_string()));
+        assert_eq!(actual.read_length, Some(4));
+        assert_eq!(actual.read_order, Some(ReadOrder::R1));
+    }
+
+    #[test]
+    fn should_read_files_using_options_of_builder() {
+        let r1 = [R1_RECORD_1, R1_RECORD_2].concat();
+        let r2 = [R2_RECORD_1, R2_RECORD_2].concat();
+        let dir = temp_dir(&[("sample_R1.fastq", &r1), ("sample_R2.fastq", &r2)]);
+        let (path_1, path_2) = (
+            dir.path().join("sample_R1.fastq"),
+            dir.path().join("sample_R2.fastq"),
+        );
+        let builder = MetadataFile::builder()
+            .checksum_type(ChecksumType::Md5)
+            .threads(2);
+
+        let actual = builder.read_file(&path_1).unwrap();
+        let (actual_1, actual_2) = builder.read_paired_files(&path_1, &path_2).unwrap();
+        let long_read = builder.long_read(true).read_file(&path_1).unwrap();
+
+        assert_eq!(actual.checksum_type, Some(ChecksumType::Md5));
+        assert_eq!(actual.file_checksum.len(), 32);
+        assert_eq!(actual.read_order, Some(ReadOrder::R1));
+        assert_eq!(actual_1.file_checksum, actual.file_checksum);
+        assert_eq!(actual_2.read_order, Some(ReadOrder::R2));
+        assert_eq!(long_read.read_length, Some(4));
+        assert_eq!(long_read.file_checksum, actual.file_checksum);
+    }
+}
@@ -0,0 +1,299 @@
+//! Reference sequences used to sample non-sensitive sequences
+
+use crate::error::Error;
+use crate::error::Error::{Io, Parse, Validation};
+use crate::input_reader;
+use std::io::{BufRead, ErrorKind};
+use std::path::PathBuf;
+
+/// Bases allowed in reference sequences
+const BASES: &[u8; 5] = b"ACGTN";
+
+/// IUPAC codes of ambiguous bases, which are read as `N`
+const AMBIGUOUS_BASES: &[u8; 10] = b"RYKMSWBDHV";
+
+/// Non-sensitive reference sequences, e.g. phiX or a synthetic genome, used to sample
+/// read sequences from.
+pub struct Reference {
+    contigs: Vec<Contig>,
+}
+
+struct Contig {
+    name: String,
+    sequence: Vec<u8>,
+}
+
+impl Reference {
+    /// Reads the reference from a FASTA file, compressed files are decompressed
+    pub fn read_file(path: PathBuf) -> Result<Reference, Error> {
+        Reference::read(input_reader(Some(path), true, 1)?)
+    }
+
+    /// Reads the reference from FASTA content. Sequences must consist of bases `ACGTN`,
+    /// IUPAC codes of ambiguous bases are read as `N`.
+    pub fn read(mut reader: impl BufRead) -> Result<Reference, Error> {
+        let mut buf = String::new();
+        let mut contigs: Vec<Contig> = vec![];
+
+        let mut line = 1;
+        loop {
+            let n = reader.read_line(&mut buf).map_err(|err| match err.kind() {
+                ErrorKind::InvalidData => {
+                    Parse(format!("Invalid UTF-8 in reference FASTA at line {}", line))
+                }
+                _ => Io(format!("Cannot read reference FASTA at line {}", line)),
+            })?;
+            if n == 0 {
+                break;
+            }
+
+            if let Some(name) = buf.strip_prefix(">") {
+                contigs.push(Contig {
+                    name: name
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    sequence: vec![],
+                });
+            } else if let Some(contig) = contigs.last_mut() {
+                let bases = buf.trim().bytes().map(|b| match b.to_ascii_uppercase() {
+                    b if AMBIGUOUS_BASES.contains(&b) => b'N',
+                    b => b,
+                });
+                if let Some(base) = bases.clone().find(|b| !BASES.contains(b)) {
+                    return Err(Parse(format!(
+                        "Invalid base '{}' in reference FASTA at line {}",
+                        base.escape_ascii(),
+                        line
+                    )));
+                }
+                contig.sequence.extend(bases);
+            } else if !buf.trim().is_empty() {
+                return Err(Parse(format!("Invalid reference FASTA at line {}", line)));
+            }
+
+            line += 1;
+            buf.clear();
+        }
+
+        if let Some(contig) = contigs.iter().find(|contig| contig.sequence.is_empty()) {
+            return Err(Parse(format!(
+                "Reference contig '{}' is empty",
+                contig.name
+            )));
+        }
+
+        if contigs.is_empty() {
+            return Err(Parse("Reference does not contain any sequence".to_string()));
+        }
+
+        Ok(Reference { contigs })
+    }
+
+    /// Returns a sequence of given length sampled from forward or reverse strand of the
+    /// reference. Equal seeds will result in equal sequences.
+    pub fn sample(&self, len: usize, seed: u64) -> Result<String, Error> {
+        if len == 0 {
+            return Ok(String::new());
+        }
+
+        let mut random = Random::new(seed);
+        let (contig, start) = self.position(len, &mut random)?;
+        let sequence = &contig.sequence[start..start + len];
+
+        if random.next().is_multiple_of(2) {
+            Ok(String::from_utf8_lossy(sequence).to_string())
+        } else {
+            Ok(reverse_complement(sequence))
+        }
+    }
+
+    /// Returns sequences for both mates of a read pair sampled from the same fragment of the
+    /// reference, with R2 on the opposite strand of R1.
+    pub fn sample_pair(
+        &self,
+        len_1: usize,
+        len_2: usize,
+        seed: u64,
+    ) -> Result<(String, String), Error> {
+        let min_len = len_1.max(len_2);
+        if min_len == 0 {
+            return Ok((String::new(), String::new()));
+        }
+
+        let mut random = Random::new(seed);
+        let max_len = self
+            .contigs
+            .iter()
+            .map(|contig| contig.sequence.len())
+            .max()
+            .unwrap_or_default();
+        let fragment_len = (min_len + (random.next() % 300) as usize)
+            .min(max_len)
+            .max(min_len);
+        let (contig, start) = self.position(fragment_len, &mut random)?;
+        let fragment = &contig.sequence[start..start + fragment_len];
+
+        let forward = String::from_utf8_lossy(&fragment[..len_1]).to_string();
+        let reverse = reverse_complement(&fragment[fragment_len - len_2..]);
+
+        if random.next().is_multiple_of(2) {
+            Ok((forward, reverse))
+        } else {
+            // Swap strands by sampling R1 from the end of the fragment
+            let reverse = reverse_complement(&fragment[fragment_len - len_1..]);
+            let forward = String::from_utf8_lossy(&fragment[..len_2]).to_string();
+            Ok((reverse, forward))
+        }
+    }
+
+    /// Returns the bases of named contig starting at zero-based start position
+    pub fn bases(&self, name: &str, start: usize, len: usize) -> Option<&[u8]> {
+        self.contigs
+            .iter()
+            .find(|contig| contig.name == name)?
+            .sequence
+            .get(start..start + len)
+    }
+
+    /// Returns the length of named contig
+    pub fn contig_len(&self, name: &str) -> Option<usize> {
+        self.contigs
+            .iter()
+            .find(|contig| contig.name == name)
+            .map(|contig| contig.sequence.len())
+    }
+
+    fn position(&self, len: usize, random: &mut Random) -> Result<(&Contig, usize), Error> {
+        let positions = |contig: &Contig| (contig.sequence.len() + 1).saturating_sub(len);
+
+        let total = self.contigs.iter().map(positions).sum::<usize>();
+        if total == 0 {
+            return Err(Validation(format!(
+                "Reference does not contain a sequence of length {}",
+                len
+            )));
+        }
+
+        let mut position = (random.next() % total as u64) as usize;
+        for contig in &self.contigs {
+            if position < positions(contig) {
+                return Ok((contig, position));
+            }
+            position -= positions(contig);
+        }
+
+        unreachable!("Position is always within total number of positions")
+    }
+}
+
+pub(crate) fn reverse_complement(sequence: &[u8]) -> String {
+    sequence
+        .iter()
+        .rev()
+        .map(|b| match b {
+            b'A' => 'T',
+            b'C' => 'G',
+            b'G' => 'C',
+            b'T' => 'A',
+            _ => 'N',
+        })
+        .collect()
+}
+
+/// Simple SplitMix64 generator to get reproducible pseudo random numbers
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random(seed)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error::Parse;
+    use crate::reference::{Reference, reverse_complement};
+
+    const REFERENCE: &str = ">chrT test contig\nACGTACGGTTAACCGGTTCA\nGGCATTACGATCGATCGA\n";
+
+    #[test]
+    fn should_sample_reproducible_sequence_from_reference() {
+        let reference = Reference::read(REFERENCE.as_bytes()).unwrap();
+        let actual = reference.sample(10, 42).unwrap();
+
+        assert_eq!(actual.len(), 10);
+        assert_eq!(actual, reference.sample(10, 42).unwrap());
+        assert!(
+            REFERENCE.replace("\n", "").contains(&actual)
+                || REFERENCE
+                    .replace("\n", "")
+                    .contains(&reverse_complement(actual.as_bytes()))
+        );
+    }
+
+    #[test]
+    fn should_not_read_reference_with_invalid_utf8() {
+        let actual = Reference::read(b">chrT\nACGT\nAC\xffGT\nACGT\n".as_slice());
+
+        assert_eq!(
+            actual.err(),
+            Some(Parse(
+                "Invalid UTF-8 in reference FASTA at line 3".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn should_not_read_reference_with_invalid_bases() {
+        let actual = Reference::read(">chrT\nACGT\nACÉGT\n".as_bytes());
+
+        assert_eq!(
+            actual.err(),
+            Some(Parse(
+                "Invalid base '\\xc3' in reference FASTA at line 3".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn should_read_ambiguous_bases_as_n() {
+        let reference = Reference::read(">chrT\nACGTRYKM\nswbdhvNn\n".as_bytes()).unwrap();
+
+        assert_eq!(
+            reference.bases("chrT", 0, 16),
+            Some(b"ACGTNNNNNNNNNNNN".as_slice())
+        );
+    }
+
+    #[test]
+    fn should_sample_empty_sequences_for_empty_reads() {
+        let reference = Reference::read(REFERENCE.as_bytes()).unwrap();
+
+        assert_eq!(reference.sample(0, 1), Ok(String::new()));
+        assert_eq!(
+            reference.sample_pair(0, 0, 1),
+            Ok((String::new(), String::new()))
+        );
+        let (actual_1, actual_2) = reference.sample_pair(0, 10, 1).unwrap();
+        assert_eq!((actual_1.len(), actual_2.len()), (0, 10));
+    }
+
+    #[test]
+    fn should_not_sample_sequence_longer_than_reference() {
+        let reference = Reference::read(REFERENCE.as_bytes()).unwrap();
+
+        assert!(reference.sample(39, 1).is_err());
+        assert!(reference.sample_pair(38, 39, 1).is_err());
+        assert!(reference.sample_pair(38, 38, 1).is_ok());
+    }
+}
@@ -0,0 +1,815 @@
+//! Reading and scrambling of SAM and BAM files
+
+use crate::bgzf::BgzfWriter;
+use crate::error::Error;
+use crate::error::Error::{Io, Parse, Validation};
+use crate::fastq::Header;
+use crate::reference::reverse_complement;
+use crate::scramble_policy::ScramblePolicy;
+use crate::scramble_sequence;
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use sha2::{Digest, Sha256};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+
+/// Tags removed from scrambled records, as they depend on the original sequence or alignment
+/// or contain identifying values like barcodes or original qualities
+const REMOVED_TAGS: [&str; 28] = [
+    "MD", "NM", "AS", "XS", "SA", "MC", "MQ", "XA", "NH", "HI", "XM", "XN", "XO", "XG", "BC", "BZ",
+    "CO", "CQ", "CS", "E2", "MI", "OQ", "OX", "Q2", "QT", "QX", "R2", "RX",
+];
+
+/// Sequence characters in order of 4-bit encoding used in BAM files
+const BAM_BASES: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+
+/// Flags kept for unaligned records: paired, first and last segment, QC fail and duplicate
+const UNALIGNED_FLAGS: u16 = 0x1 | 0x40 | 0x80 | 0x200 | 0x400;
+
+/// Flags of secondary and supplementary alignments
+const SECONDARY_FLAGS: u16 = 0x100 | 0x800;
+
+/// Bin of unaligned BAM records as calculated by `reg2bin(-1, 0)`
+const UNALIGNED_BIN: u16 = 4680;
+
+/// Flag of records aligned to the reverse strand
+const REVERSE_FLAG: u16 = 0x10;
+
+/// Scrambles read names, sequences, qualities and identifying header lines of SAM/BAM files.
+///
+/// Scrambled sequences do not match the reference at the aligned positions, so records are
+/// converted to unaligned records. Secondary and supplementary alignments are removed, as
+/// they would duplicate reads of the primary alignment.
+pub struct AlignmentScrambler<'a> {
+    policy: &'a ScramblePolicy,
+}
+
+impl<'a> AlignmentScrambler<'a> {
+    /// Creates a scrambler using given policy for values of read names
+    pub fn new(policy: &'a ScramblePolicy) -> Self {
+        AlignmentScrambler { policy }
+    }
+
+    /// Returns scrambled header line or `None` if the line should be removed
+    pub fn header_line(&self, line: &str) -> Option<String> {
+        let mut fields = line.split('\t');
+        let record_type = fields.next()?;
+
+        match record_type {
+            "@RG" => Some(
+                std::iter::once(record_type.to_string())
+                    .chain(fields.filter_map(|field| match field.split_at_checked(3) {
+                        Some(("ID:", value)) => Some(format!("ID:{}", identifier("RG", value))),
+                        Some(("SM:", value)) => Some(format!("SM:{}", identifier("SM", value))),
+                        Some(("LB:", value)) => Some(format!("LB:{}", identifier("LB", value))),
+                        Some(("PU:", value)) => Some(format!("PU:{}", identifier("PU", value))),
+                        Some(("PL:", _)) | Some(("PM:", _)) => Some(field.to_string()),
+                        _ => None,
+                    }))
+                    .collect::<Vec<_>>()
+                    .join("\t"),
+            ),
+            "@PG" => Some(
+                std::iter::once(record_type.to_string())
+                    .chain(
+                        fields
+                            .filter(|field| !field.starts_with("CL:") && !field.starts_with("DS:"))
+                            .map(|field| field.to_string()),
+                    )
+                    .collect::<Vec<_>>()
+                    .join("\t"),
+            ),
+            "@SQ" => Some(
+                std::iter::once(record_type.to_string())
+                    .chain(
+                        fields
+                            .filter(|field| !field.starts_with("UR:") && !field.starts_with("DS:"))
+                            .map(|field| field.to_string()),
+                    )
+                    .collect::<Vec<_>>()
+                    .join("\t"),
+            ),
+            "@CO" => None,
+            _ => Some(line.to_string()),
+        }
+    }
+
+    /// Returns scrambled read name. Illumina read names are scrambled using the scramble
+    /// policy, other names are replaced by a name derived from its hash.
+    pub fn read_name(&self, name: &str) -> String {
+        match format!("@{name} 1:N:0:N").parse::<Header>() {
+            Ok(header) => {
+                let header = header.scramble_with(self.policy).to_string();
+                match header[1..].split_once(' ') {
+                    Some((name, _)) => name.to_string(),
+                    None => identifier("READ", name),
+                }
+            }
+            Err(_) => identifier("READ", name),
+        }
+    }
+
+    /// Returns scrambled sequence of same length. Both mates of a read pair are scrambled
+    /// using the same seed derived from the read name.
+    pub fn sequence(&self, sequence: &str, name: &str) -> String {
+        scramble_sequence(sequence, seed(name))
+    }
+
+    /// Returns qualities rotated by a seed derived from the read name
+    pub fn quality(&self, quality: &[u8], name: &str) -> Vec<u8> {
+        let mut quality = quality.to_vec();
+        if !quality.is_empty() {
+            let len = quality.len();
+            quality.rotate_left(seed(name) as usize % len);
+        }
+        quality
+    }
+
+    fn keep_tag(name: &str) -> bool {
+        !REMOVED_TAGS.contains(&name)
+    }
+}
+
+/// Returns the flags of the unaligned record, mates of paired records are unaligned as well
+fn unaligned_flag(flag: u16) -> u16 {
+    let flag = flag & UNALIGNED_FLAGS | 0x4;
+    if flag & 0x1 != 0 { flag | 0x8 } else { flag }
+}
+
+/// Returns sequence and qualities in the orientation of the read. Records aligned to the
+/// reverse strand contain the reverse complemented sequence and reversed qualities.
+fn read_orientation(flag: u16, sequence: &str, quality: &[u8]) -> (String, Vec<u8>) {
+    if flag & REVERSE_FLAG == 0 {
+        return (sequence.to_string(), quality.to_vec());
+    }
+    (
+        reverse_complement(sequence.as_bytes()),
+        quality.iter().rev().copied().collect(),
+    )
+}
+
+/// Returns an identifier with given prefix derived from the hash of the value
+fn identifier(prefix: &str, value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(value.as_bytes());
+    let hash = hasher.finalize();
+    format!("{prefix}{}", base16ct::upper::encode_string(&hash[..6]))
+}
+
+fn seed(name: &str) -> u32 {
+    name.bytes().map(|b| b as u32).sum::<u32>() % 97
+}
+
+/// Scrambles SAM input.
+///
+/// Records that cannot be scrambled are omitted and passed to `on_failure` with the line
+/// number and the error. This will result in an error after all records have been processed.
+pub fn scramble_sam(
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    policy: &ScramblePolicy,
+    mut on_failure: impl FnMut(usize, &Error),
+) -> Result<(), Error> {
+    let scrambler = AlignmentScrambler::new(policy);
+    let mut buf = String::new();
+    let mut failed = 0;
+    let mut first_error = None;
+
+    let mut line = 1;
+    loop {
+        let n = reader.read_line(&mut buf).map_err(|err| match err.kind() {
+            ErrorKind::InvalidData => Parse(format!("Invalid UTF-8 in SAM input at line {}", line)),
+            _ => Io(format!("Cannot read SAM input at line {}", line)),
+        })?;
+        if n == 0 {
+            break;
+        }
+
+        let value = buf.trim_end_matches(['\n', '\r']);
+
+        let scrambled = if value.starts_with('@') {
+            scrambler.header_line(value)
+        } else {
+            match scramble_sam_record(&scrambler, value, line) {
+                Ok(record) => record,
+                Err(err) => {
+                    failed += 1;
+                    on_failure(line, &err);
+                    first_error.get_or_insert(err);
+                    None
+                }
+            }
+        };
+
+        if let Some(scrambled) = scrambled {
+            writeln!(writer, "{scrambled}").map_err(|_| Io("Cannot write output".to_string()))?;
+        }
+
+        line += 1;
+        buf.clear();
+    }
+
+    writer
+        .flush()
+        .map_err(|_| Io("Cannot write output".to_string()))?;
+
+    if let Some(err) = first_error {
+        return Err(err.with_message(format!("{failed} record(s) could not be scrambled")));
+    }
+
+    Ok(())
+}
+
+/// Returns the scrambled unaligned SAM record or `None` for removed secondary and
+/// supplementary alignments
+fn scramble_sam_record(
+    scrambler: &AlignmentScrambler,
+    value: &str,
+    line: usize,
+) -> Result<Option<String>, Error> {
+    let fields = value.split('\t').collect::<Vec<_>>();
+    let flag = match fields.get(1).map(|flag| flag.parse::<u16>()) {
+        Some(Ok(flag)) if fields.len() >= 11 => flag,
+        _ => return Err(Parse(format!("Invalid SAM record at line {}", line))),
+    };
+
+    if flag & SECONDARY_FLAGS != 0 {
+        return Ok(None);
+    }
+
+    let name = fields[0];
+    let (sequence, quality) = read_orientation(flag, fields[9], fields[10].as_bytes());
+    let sequence = match fields[9] {
+        "*" => "*".to_string(),
+        _ => scrambler.sequence(&sequence, name),
+    };
+    let quality = match fields[10] {
+        "*" => "*".to_string(),
+        _ => String::from_utf8_lossy(&scrambler.quality(&quality, name)).to_string(),
+    };
+
+    let tags = fields[11..]
+        .iter()
+        .filter_map(|tag| match tag.split_at_checked(2) {
+            Some(("RG", value)) => match value.strip_prefix(":Z:") {
+                Some(value) => Some(format!("RG:Z:{}", identifier("RG", value))),
+                None => Some(tag.to_string()),
+            },
+            Some((name, _)) if !AlignmentScrambler::keep_tag(name) => None,
+            _ => Some(tag.to_string()),
+        });
+
+    // Alignment fields RNAME, POS, MAPQ, CIGAR, RNEXT, PNEXT and TLEN of unaligned records
+    let alignment = ["*", "0", "0", "*", "*", "0", "0"].map(String::from);
+
+    Ok(Some(
+        [scrambler.read_name(name), unaligned_flag(flag).to_string()]
+            .into_iter()
+            .chain(alignment)
+            .chain([sequence, quality])
+            .chain(tags)
+            .collect::<Vec<_>>()
+            .join("\t"),
+    ))
+}
+
+/// Scrambles BGZF compressed BAM input and writes BGZF compressed BAM output.
+///
+/// Records that cannot be scrambled are omitted and passed to `on_failure` with the record
+/// number and the error. This will result in an error after all records have been processed.
+pub fn scramble_bam(
+    reader: impl Read,
+    writer: impl Write,
+    policy: &ScramblePolicy,
+    threads: usize,
+    mut on_failure: impl FnMut(usize, &Error),
+) -> Result<(), Error> {
+    let scrambler = AlignmentScrambler::new(policy);
+    let mut reader = BamReader::new(reader)?;
+    let mut writer = BgzfWriter::new(writer, Compression::default(), threads);
+
+    let text = reader
+        .header
+        .lines()
+        .filter_map(|line| scrambler.header_line(line))
+        .map(|line| format!("{line}\n"))
+        .collect::<String>();
+
+    let mut header = vec![];
+    header.extend_from_slice(b"BAM\x01");
+    header.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    header.extend_from_slice(text.as_bytes());
+    header.extend_from_slice(&reader.references);
+    writer
+        .write_all(&header)
+        .map_err(|_| Io("Cannot write output".to_string()))?;
+
+    let mut number = 0;
+    let mut failed = 0;
+    let mut first_error = None;
+    while let Some(record) = reader.read_record()? {
+        number += 1;
+        if record.flag() & SECONDARY_FLAGS != 0 {
+            continue;
+        }
+
+        match scramble_bam_record(&scrambler, record).to_bytes() {
+            Ok(data) => writer
+                .write_all(&data)
+                .map_err(|_| Io("Cannot write output".to_string()))?,
+            Err(err) => {
+                failed += 1;
+                on_failure(number, &err);
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    writer
+        .finish()
+        .map_err(|_| Io("Cannot write output".to_string()))?;
+
+    if let Some(err) = first_error {
+        return Err(err.with_message(format!("{failed} record(s) could not be scrambled")));
+    }
+
+    Ok(())
+}
+
+/// Returns the scrambled unaligned BAM record
+fn scramble_bam_record(scrambler: &AlignmentScrambler, record: BamRecord) -> BamRecord {
+    let name = record.read_name.clone();
+    let (sequence, quality) = read_orientation(record.flag(), &record.sequence, &record.quality);
+
+    BamRecord {
+        fixed: record.unaligned_fixed(),
+        cigar: vec![],
+        read_name: scrambler.read_name(&name),
+        sequence: scrambler.sequence(&sequence, &name),
+        quality: match quality.first() {
+            Some(0xff) => quality,
+            _ => scrambler.quality(&quality, &name),
+        },
+        tags: record
+            .tags
+            .into_iter()
+            .filter(|tag| AlignmentScrambler::keep_tag(&tag.name))
+            .map(|tag| match (tag.name.as_str(), tag.value_type) {
+                ("RG", b'Z') => {
+                    let value = String::from_utf8_lossy(&tag.value[..tag.value.len() - 1]);
+                    let mut value = identifier("RG", &value).into_bytes();
+                    value.push(0);
+                    Tag { value, ..tag }
+                }
+                _ => tag,
+            })
+            .collect(),
+    }
+}
+
+/// Reads header and records of BGZF compressed BAM input
+pub struct BamReader<R: Read> {
+    reader: BufReader<MultiGzDecoder<R>>,
+    /// Header text in SAM format
+    pub header: String,
+    /// Raw reference sequence dictionary following the header text
+    references: Vec<u8>,
+}
+
+impl<R: Read> BamReader<R> {
+    /// Creates the reader and reads the BAM header of given BGZF compressed input
+    pub fn new(reader: R) -> Result<Self, Error> {
+        let mut reader = BufReader::new(MultiGzDecoder::new(reader));
+
+        let mut magic = [0; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| Parse("Invalid BAM file".to_string()))?;
+        if &magic != b"BAM\x01" {
+            return Err(Parse("Invalid BAM file".to_string()));
+        }
+
+        let l_text = read_u32(&mut reader)? as usize;
+        let mut text = vec![0; l_text];
+        reader
+            .read_exact(&mut text)
+            .map_err(|_| Parse("Invalid BAM header".to_string()))?;
+        let header = String::from_utf8_lossy(&text)
+            .trim_end_matches('\0')
+            .to_string();
+
+        let n_ref = read_u32(&mut reader)?;
+        let mut references = n_ref.to_le_bytes().to_vec();
+        for _ in 0..n_ref {
+            let l_name = read_u32(&mut reader)?;
+            let mut reference = vec![0; l_name as usize + 4];
+            reader
+                .read_exact(&mut reference)
+                .map_err(|_| Parse("Invalid BAM header".to_string()))?;
+            references.extend_from_slice(&l_name.to_le_bytes());
+            references.extend_from_slice(&reference);
+        }
+
+        Ok(BamReader {
+            reader,
+            header,
+            references,
+        })
+    }
+
+    /// Returns the next record or `None` at end of input
+    pub fn read_record(&mut self) -> Result<Option<BamRecord>, Error> {
+        let mut block_size = [0; 4];
+        match self.reader.read(&mut block_size[..1]) {
+            Ok(0) => return Ok(None),
+            Ok(_) => self
+                .reader
+                .read_exact(&mut block_size[1..])
+                .map_err(|_| Parse("Invalid BAM record".to_string()))?,
+            Err(_) => return Err(Io("Cannot read BAM record".to_string())),
+        }
+
+        let mut data = vec![0; u32::from_le_bytes(block_size) as usize];
+        self.reader
+            .read_exact(&mut data)
+            .map_err(|_| Parse("Invalid BAM record".to_string()))?;
+
+        BamRecord::from_bytes(data).map(Some)
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, Error> {
+    let mut buf = [0; 4];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|_| Parse("Invalid BAM header".to_string()))?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// A single BAM record. Alignment data is kept as is unless converted to an unaligned record.
+pub struct BamRecord {
+    /// Fixed length fields from `refID` to `tlen`
+    fixed: [u8; 32],
+    /// Read name without the trailing NUL
+    pub read_name: String,
+    cigar: Vec<u8>,
+    /// Sequence of bases
+    pub sequence: String,
+    /// Phred quality values without offset
+    pub quality: Vec<u8>,
+    tags: Vec<Tag>,
+}
+
+struct Tag {
+    name: String,
+    value_type: u8,
+    value: Vec<u8>,
+}
+
+impl BamRecord {
+    /// Returns the bitwise flags of this record
+    pub fn flag(&self) -> u16 {
+        u16::from_le_bytes([self.fixed[14], self.fixed[15]])
+    }
+
+    /// Returns fixed length fields of the record converted to an unaligned record
+    fn unaligned_fixed(&self) -> [u8; 32] {
+        let mut fixed = self.fixed;
+        fixed[0..8].copy_from_slice(&[0xff; 8]);
+        fixed[9] = 0;
+        fixed[10..12].copy_from_slice(&UNALIGNED_BIN.to_le_bytes());
+        fixed[12..14].copy_from_slice(&0u16.to_le_bytes());
+        fixed[14..16].copy_from_slice(&unaligned_flag(self.flag()).to_le_bytes());
+        fixed[20..28].copy_from_slice(&[0xff; 8]);
+        fixed[28..32].copy_from_slice(&0i32.to_le_bytes());
+        fixed
+    }
+
+    fn from_bytes(data: Vec<u8>) -> Result<BamRecord, Error> {
+        let err = || Parse("Invalid BAM record".to_string());
+
+        let fixed: [u8; 32] = data.get(..32).ok_or_else(err)?.try_into().unwrap();
+        let l_read_name = fixed[8] as usize;
+        let n_cigar_op = u16::from_le_bytes([fixed[12], fixed[13]]) as usize;
+        let l_seq = u32::from_le_bytes([fixed[16], fixed[17], fixed[18], fixed[19]]) as usize;
+
+        let mut offset = 32;
+        let mut next = |len: usize| {
+            let value = data.get(offset..offset + len).ok_or_else(err);
+            offset += len;
+            value
+        };
+
+        let read_name = next(l_read_name)?;
+        let read_name = String::from_utf8_lossy(read_name)
+            .trim_end_matches('\0')
+            .to_string();
+        let cigar = next(n_cigar_op * 4)?.to_vec();
+        let sequence = next(l_seq.div_ceil(2))?
+            .iter()
+            .flat_map(|b| [BAM_BASES[(b >> 4) as usize], BAM_BASES[(b & 0xf) as usize]])
+            .take(l_seq)
+            .map(|b| b as char)
+            .collect::<String>();
+        let quality = next(l_seq)?.to_vec();
+
+        let mut tags = vec![];
+        let mut tag_data = &data[offset..];
+        while !tag_data.is_empty() {
+            let (tag, len) = Tag::from_bytes(tag_data).ok_or_else(err)?;
+            tags.push(tag);
+            tag_data = &tag_data[len..];
+        }
+
+        Ok(BamRecord {
+            fixed,
+            read_name,
+            cigar,
+            sequence,
+            quality,
+            tags,
+        })
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let l_read_name = u8::try_from(self.read_name.len() + 1).map_err(|_| {
+            Validation(format!(
+                "Read name '{}' is longer than 254 characters",
+                self.read_name
+            ))
+        })?;
+
+        let mut data = vec![];
+        data.extend_from_slice(&self.fixed);
+        data[8] = l_read_name;
+        data.extend_from_slice(self.read_name.as_bytes());
+        data.push(0);
+        data.extend_from_slice(&self.cigar);
+        self.sequence
+            .as_bytes()
+            .chunks(2)
+            .map(|bases| {
+                let code = |base: u8| {
+                    BAM_BASES
+                        .iter()
+                        .position(|b| *b == base.to_ascii_uppercase())
+                        .unwrap_or(15) as u8
+                };
+                (code(bases[0]) << 4) | bases.get(1).map(|b| code(*b)).unwrap_or(0)
+            })
+            .for_each(|b| data.push(b));
+        data.extend_from_slice(&self.quality);
+        for tag in &self.tags {
+            data.extend_from_slice(tag.name.as_bytes());
+            data.push(tag.value_type);
+            data.extend_from_slice(&tag.value);
+        }
+
+        let mut record = (data.len() as u32).to_le_bytes().to_vec();
+        record.extend(data);
+        Ok(record)
+    }
+}
+
+impl Tag {
+    /// Returns the tag and the number of bytes used
+    fn from_bytes(data: &[u8]) -> Option<(Tag, usize)> {
+        let name = String::from_utf8_lossy(data.get(..2)?).to_string();
+        let value_type = *data.get(2)?;
+
+        fn size(value_type: u8) -> Option<usize> {
+            match value_type {
+                b'A' | b'c' | b'C' => Some(1),
+                b's' | b'S' => Some(2),
+                b'i' | b'I' | b'f' => Some(4),
+                _ => None,
+            }
+        }
+
+        let len = match value_type {
+            b'Z' | b'H' => data[3..].iter().position(|b| *b == 0)? + 1,
+            b'B' => {
+                let count = u32::from_le_bytes(data.get(4..8)?.try_into().ok()?) as usize;
+                5 + count * size(*data.get(3)?)?
+            }
+            value_type => size(value_type)?,
+        };
+
+        let value = data.get(3..3 + len)?.to_vec();
+
+        Some((
+            Tag {
+                name,
+                value_type,
+                value,
+            },
+            3 + len,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error::{Parse, Validation};
+    use crate::sam::{AlignmentScrambler, BamRecord, scramble_bam_record, scramble_sam};
+    use crate::scramble_policy::ScramblePolicy;
+
+    #[test]
+    fn should_not_scramble_sam_records_with_invalid_utf8() {
+        let given = b"@HD\tVN:1.6\n\
+            read1\t4\t*\t0\t0\t*\t*\t0\t0\tGATT\tII\xffJ\n";
+
+        let mut actual = vec![];
+        let result = scramble_sam(
+            given.as_slice(),
+            &mut actual,
+            &ScramblePolicy::default(),
+            |_, _| {},
+        );
+
+        assert_eq!(
+            result,
+            Err(Parse("Invalid UTF-8 in SAM input at line 2".to_string()))
+        );
+    }
+
+    #[test]
+    fn should_scramble_sam_records() {
+        let given = "@HD\tVN:1.6\n\
+            @SQ\tSN:chr1\tLN:1000\tAS:GRCh38\tUR:file:///home/user/patient_001/ref.fa\n\
+            @RG\tID:rg1\tSM:patient_001\tPL:ILLUMINA\tDS:secret\n\
+            @PG\tID:bwa\tPN:bwa\tCL:bwa mem /data/patient_001.fastq\n\
+            @CO\tpatient 001\n\
+            EAS139:136:FC706VJ:2:2104:15343:197393\t99\tchr1\t100\t60\t4M\t=\t200\t104\tGATT\tIIJJ\tMD:Z:4\tRG:Z:rg1\tAS:i:4\tXT:A:U\n\
+            EAS139:136:FC706VJ:2:2104:15343:197393\t355\tchr1\t500\t0\t4M\t=\t200\t104\tGATT\tIIJJ\n\
+            EAS139:136:FC706VJ:2:2104:15343:197393\t2147\tchr1\t700\t60\t2M2H\t=\t200\t104\tGA\tII\n";
+
+        let mut actual = vec![];
+        scramble_sam(
+            given.as_bytes(),
+            &mut actual,
+            &ScramblePolicy::default(),
+            |_, _| {},
+        )
+        .unwrap();
+        let actual = String::from_utf8(actual).unwrap();
+        let lines = actual.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "@HD\tVN:1.6");
+        assert_eq!(lines[1], "@SQ\tSN:chr1\tLN:1000\tAS:GRCh38");
+        assert_eq!(
+            lines[2],
+            "@RG\tID:RGF91308A58322\tSM:SMA5836D2E9E60\tPL:ILLUMINA"
+        );
+        assert_eq!(lines[3], "@PG\tID:bwa\tPN:bwa");
+        assert_eq!(
+            lines[4],
+            "TEST73:273:CQEAACM:8:503:15353:197403\t77\t*\t0\t0\t*\t*\t0\t0\tGGAA\tIJJI\tRG:Z:RGF91308A58322\tXT:A:U"
+        );
+    }
+
+    #[test]
+    fn should_scramble_reverse_strand_records_in_read_orientation() {
+        let given = "EAS139:136:FC706VJ:2:2104:15343:197393\t0\t*\t0\t0\t*\t*\t0\t0\tAATCG\tABCDE\n\
+            EAS139:136:FC706VJ:2:2104:15343:197393\t16\tchr1\t100\t60\t5M\t*\t0\t0\tCGATT\tEDCBA\n";
+
+        let mut actual = vec![];
+        scramble_sam(
+            given.as_bytes(),
+            &mut actual,
+            &ScramblePolicy::default(),
+            |_, _| {},
+        )
+        .unwrap();
+        let actual = String::from_utf8(actual).unwrap();
+        let lines = actual
+            .lines()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines[1][1], "4");
+        assert_eq!(lines[0][9..], lines[1][9..]);
+    }
+
+    #[test]
+    fn should_pass_sam_records_that_cannot_be_scrambled_to_caller() {
+        let given = "@HD\tVN:1.6\n\
+            read1\t4\t*\t0\t0\t*\t*\t0\t0\tGATT\tIIII\n\
+            read2\tinvalid\n\
+            read3\t4\t*\t0\t0\t*\t*\t0\t0\tGATT\tIIII\n";
+
+        let mut failures = vec![];
+        let mut actual = vec![];
+        let result = scramble_sam(
+            given.as_bytes(),
+            &mut actual,
+            &ScramblePolicy::default(),
+            |line, err| failures.push((line, err.to_string())),
+        );
+
+        assert_eq!(
+            result,
+            Err(Parse("1 record(s) could not be scrambled".to_string()))
+        );
+        assert_eq!(
+            failures,
+            vec![(3, "Invalid SAM record at line 3".to_string())]
+        );
+        assert_eq!(String::from_utf8(actual).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn should_keep_bam_record_bytes() {
+        let mut given = vec![0u8; 32];
+        given[8] = 5;
+        given[12] = 1;
+        given[16] = 3;
+        given.extend_from_slice(b"read\0");
+        given.extend_from_slice(&[0x30, 0, 0, 0]);
+        given.extend_from_slice(&[0x12, 0x40]);
+        given.extend_from_slice(&[30, 31, 32]);
+        given.extend_from_slice(b"RGZrg1\0NMC\x01");
+
+        let actual = BamRecord::from_bytes(given.clone()).unwrap();
+        assert_eq!(actual.read_name, "read");
+        assert_eq!(actual.sequence, "ACG");
+        assert_eq!(actual.tags.len(), 2);
+        assert_eq!(&actual.to_bytes().unwrap()[4..], given.as_slice());
+        assert!(!AlignmentScrambler::keep_tag(&actual.tags[1].name));
+    }
+
+    #[test]
+    fn should_convert_bam_record_to_unaligned_record() {
+        let mut given = vec![0u8; 32];
+        given[4] = 99;
+        given[8] = 5;
+        given[9] = 60;
+        given[12] = 1;
+        given[14] = 0x63;
+        given[16] = 3;
+        given[24] = 199;
+        given[28] = 104;
+        given.extend_from_slice(b"read\0");
+        given.extend_from_slice(&[0x30, 0, 0, 0]);
+        given.extend_from_slice(&[0x12, 0x40]);
+        given.extend_from_slice(&[30, 31, 32]);
+
+        let record = BamRecord::from_bytes(given).unwrap();
+        let actual = BamRecord {
+            fixed: record.unaligned_fixed(),
+            cigar: vec![],
+            ..record
+        };
+
+        assert_eq!(actual.flag(), 0x4d);
+        assert_eq!(
+            &actual.to_bytes().unwrap()[4..36],
+            &[
+                0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 5, 0, 0x48, 0x12, 0, 0, 0x4d, 0, 3,
+                0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0
+            ]
+        );
+    }
+
+    #[test]
+    fn should_scramble_reverse_strand_bam_records_in_read_orientation() {
+        let record = |flag: u8, sequence: [u8; 2], quality: [u8; 3]| {
+            let mut data = vec![0u8; 32];
+            data[8] = 5;
+            data[14] = flag;
+            data[16] = 3;
+            data.extend_from_slice(b"read\0");
+            data.extend_from_slice(&sequence);
+            data.extend_from_slice(&quality);
+            BamRecord::from_bytes(data).unwrap()
+        };
+        let policy = ScramblePolicy::default();
+        let scrambler = AlignmentScrambler::new(&policy);
+
+        // Forward record 'AAC' and reverse strand record 'GTT' of the same read
+        let forward = scramble_bam_record(&scrambler, record(0, [0x11, 0x20], [30, 31, 32]));
+        let reverse = scramble_bam_record(&scrambler, record(0x10, [0x48, 0x80], [32, 31, 30]));
+
+        assert_eq!(reverse.flag(), 0x4);
+        assert_eq!(reverse.sequence, forward.sequence);
+        assert_eq!(reverse.quality, forward.quality);
+    }
+
+    #[test]
+    fn should_return_error_for_too_long_bam_read_names() {
+        let mut given = vec![0u8; 32];
+        given[8] = 5;
+        given.extend_from_slice(b"read\0");
+        let record = BamRecord::from_bytes(given).unwrap();
+
+        let actual = BamRecord {
+            read_name: "R".repeat(255),
+            ..record
+        };
+
+        assert!(matches!(
+            actual.to_bytes(),
+            Err(Validation(err)) if err.ends_with("is longer than 254 characters")
+        ));
+    }
+}
@@ -0,0 +1,242 @@
+//! Scrambling of FASTQ records
+
+use crate::error::Error;
+use crate::error::Error::{Io, Validation};
+use crate::fastq::{Record, RecordReader};
+use crate::reference::Reference;
+use crate::scramble_policy::ScramblePolicy;
+use crate::threaded::{BATCH_SIZE, process_ordered};
+use std::io::{BufRead, Write};
+
+/// Scrambles the reads of R1 and R2 input in lockstep.
+///
+/// Both mates of a read pair get the same header transformation and the same seed for
+/// sequence scrambling, so scrambled files still form a valid pair. If a reference is given,
+/// sequences of both mates are sampled from the same fragment of the reference.
+///
+/// Read pairs that cannot be scrambled are omitted and passed to `on_failure` with the
+/// header line number and the error. This will result in an error after all read pairs
+/// have been processed.
+#[allow(clippy::too_many_arguments)]
+pub fn scramble_paired(
+    reader_1: impl BufRead,
+    reader_2: impl BufRead,
+    mut writer_1: impl Write,
+    mut writer_2: impl Write,
+    reference: Option<&Reference>,
+    policy: &ScramblePolicy,
+    threads: usize,
+    mut on_failure: impl FnMut(usize, &Error),
+) -> Result<(), Error> {
+    let mut records_1 = RecordReader::new(reader_1);
+    let mut records_2 = RecordReader::new(reader_2);
+
+    let pairs = std::iter::from_fn(|| match (records_1.next(), records_2.next()) {
+        (None, None) => None,
+        (Some(record_1), Some(record_2)) => Some(
+            // Use sequence line number as seed for both mates
+            record_1.and_then(|record_1| Ok((records_1.line() - 2, record_1, record_2?))),
+        ),
+        _ => Some(Err(Validation(format!(
+            "Paired input contains different number of records at line {}",
+            records_1.line().max(records_2.line()) + 1
+        )))),
+    });
+
+    let scramble_pair = |(line, record_1, record_2): (usize, Record, Record)| {
+        if !record_1.header()?.is_mate_of(&record_2.header()?) {
+            return Err(Validation("Reads are not mates".to_string()));
+        }
+
+        let scrambled_1 = record_1.scramble(line as u32 % 97, policy)?;
+        let scrambled_2 = record_2.scramble(line as u32 % 97, policy)?;
+
+        let (scrambled_1, scrambled_2) = match reference {
+            Some(reference) => {
+                let (sequence_1, sequence_2) = reference.sample_pair(
+                    scrambled_1.sequence().len(),
+                    scrambled_2.sequence().len(),
+                    line as u64,
+                )?;
+                (
+                    scrambled_1.with_sequence(sequence_1),
+                    scrambled_2.with_sequence(sequence_2),
+                )
+            }
+            None => (scrambled_1, scrambled_2),
+        };
+
+        scrambled_1.verify()?;
+        scrambled_2.verify()?;
+
+        Ok((scrambled_1, scrambled_2))
+    };
+
+    let mut failed = 0;
+    let mut first_error = None;
+    process_ordered(
+        pairs,
+        BATCH_SIZE,
+        threads,
+        |pair| pair.map(|pair| (pair.0, scramble_pair(pair))),
+        |scrambled| {
+            match scrambled? {
+                (_, Ok((scrambled_1, scrambled_2))) => {
+                    write!(writer_1, "{scrambled_1}")
+                        .map_err(|_| Io("Cannot write R1 output".to_string()))?;
+                    write!(writer_2, "{scrambled_2}")
+                        .map_err(|_| Io("Cannot write R2 output".to_string()))?;
+                }
+                (line, Err(err)) => {
+                    failed += 1;
+                    on_failure(line - 1, &err);
+                    first_error.get_or_insert(err);
+                }
+            }
+            Ok::<(), Error>(())
+        },
+    )?;
+
+    writer_1
+        .flush()
+        .map_err(|_| Io("Cannot write R1 output".to_string()))?;
+    writer_2
+        .flush()
+        .map_err(|_| Io("Cannot write R2 output".to_string()))?;
+
+    // Failed pairs result in an error of the category of the first failure
+    if let Some(err) = first_error {
+        return Err(err.with_message(format!("{failed} read pair(s) could not be scrambled")));
+    }
+
+    Ok(())
+}
+
+/// Scrambles the records of input.
+///
+/// Every scrambled record is verified to be a well-formed FASTQ record. Records that
+/// cannot be scrambled are omitted and passed to `on_failure` with the header line number
+/// and the error. This will result in an error after all records have been processed.
+pub fn scramble(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    reference: Option<&Reference>,
+    policy: &ScramblePolicy,
+    threads: usize,
+    mut on_failure: impl FnMut(usize, &Error),
+) -> Result<(), Error> {
+    let mut records = RecordReader::new(reader);
+
+    // Use sequence line number as seed
+    let records = std::iter::from_fn(|| {
+        records
+            .next()
+            .map(|record| record.map(|record| (records.line() - 2, record)))
+    });
+
+    let scramble_record = |line: usize, record: Record| {
+        record
+            .scramble(line as u32 % 97, policy)
+            .and_then(|scrambled| match reference {
+                Some(reference) => Ok(scrambled
+                    .with_sequence(reference.sample(record.sequence().len(), line as u64)?)),
+                None => Ok(scrambled),
+            })
+            .and_then(|scrambled| scrambled.verify().map(|_| scrambled))
+    };
+
+    let mut failed = 0;
+    let mut first_error = None;
+    process_ordered(
+        records,
+        BATCH_SIZE,
+        threads,
+        |record| record.map(|(line, record)| (line, scramble_record(line, record))),
+        |scrambled| {
+            match scrambled? {
+                (_, Ok(scrambled)) => write!(writer, "{scrambled}")
+                    .map_err(|_| Io("Cannot write output".to_string()))?,
+                (line, Err(err)) => {
+                    failed += 1;
+                    on_failure(line - 1, &err);
+                    first_error.get_or_insert(err);
+                }
+            }
+            Ok::<(), Error>(())
+        },
+    )?;
+
+    writer
+        .flush()
+        .map_err(|_| Io("Cannot write output".to_string()))?;
+
+    if let Some(err) = first_error {
+        return Err(err.with_message(format!("{failed} record(s) could not be scrambled")));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error::{Parse, Validation};
+    use crate::scramble::{scramble, scramble_paired};
+    use crate::scramble_policy::ScramblePolicy;
+
+    #[test]
+    fn should_pass_records_that_cannot_be_scrambled_to_caller() {
+        let given = "@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG\nGATT\n+\nIIII\n\
+            @invalid header\nGATT\n+\nIIII\n\
+            @EAS139:136:FC706VJ:2:2104:15343:197395 1:Y:18:ATCACG\nGATT\n+\nIIII\n";
+
+        let mut failures = vec![];
+        let mut actual = vec![];
+        let result = scramble(
+            given.as_bytes(),
+            &mut actual,
+            None,
+            &ScramblePolicy::default(),
+            1,
+            |line, err| failures.push((line, err.to_string())),
+        );
+
+        assert_eq!(
+            result,
+            Err(Parse("1 record(s) could not be scrambled".to_string()))
+        );
+        assert_eq!(failures, vec![(5, "Cannot parse FASTQ header".to_string())]);
+        assert_eq!(String::from_utf8(actual).unwrap().lines().count(), 8);
+    }
+
+    #[test]
+    fn should_pass_read_pairs_that_are_not_mates_to_caller() {
+        let given_1 = "@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG\nGATT\n+\nIIII\n\
+            @EAS139:136:FC706VJ:2:2104:15343:197394 1:Y:18:ATCACG\nGATT\n+\nIIII\n";
+        let given_2 = "@EAS139:136:FC706VJ:2:2104:15343:197393 2:Y:18:ATCACG\nAATC\n+\nIIII\n\
+            @EAS139:136:FC706VJ:2:2104:15343:197395 2:Y:18:ATCACG\nAATC\n+\nIIII\n";
+
+        let mut failures = vec![];
+        let mut actual_1 = vec![];
+        let mut actual_2 = vec![];
+        let result = scramble_paired(
+            given_1.as_bytes(),
+            given_2.as_bytes(),
+            &mut actual_1,
+            &mut actual_2,
+            None,
+            &ScramblePolicy::default(),
+            1,
+            |line, err| failures.push((line, err.to_string())),
+        );
+
+        assert_eq!(
+            result,
+            Err(Validation(
+                "1 read pair(s) could not be scrambled".to_string()
+            ))
+        );
+        assert_eq!(failures, vec![(5, "Reads are not mates".to_string())]);
+        assert_eq!(String::from_utf8(actual_1).unwrap().lines().count(), 4);
+        assert_eq!(String::from_utf8(actual_2).unwrap().lines().count(), 4);
+    }
+}
@@ -0,0 +1,282 @@
+//! Policies configuring how header fields are scrambled
+
+use crate::error::Error;
+use crate::error::Error::{Io, Parse};
+use clap::ValueEnum;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+/// Policy how to handle a single header field when scrambling
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldPolicy {
+    /// Keep the original value
+    Keep,
+    /// Scramble the value as done by default
+    #[default]
+    Scramble,
+    /// Replace the value by a value derived from its SHA-256 hash
+    Hash,
+    /// Replace the value by a constant value
+    Constant(String),
+}
+
+/// Header fields of `Casava18Header` and `IlluminaHeader` a policy can be applied to
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum HeaderField {
+    /// Instrument name or ID
+    InstrumentName,
+    /// Run number of the instrument
+    RunId,
+    /// Flowcell ID
+    FlowcellId,
+    /// Lane of the flowcell
+    FlowcellLane,
+    /// Tile number within the lane
+    TileNumber,
+    /// X coordinate of the cluster within the tile
+    X,
+    /// Y coordinate of the cluster within the tile
+    Y,
+    /// Index sequence of Casava 1.8+ headers
+    IndexSequence,
+    /// Index number of older Illumina headers
+    IndexNumber,
+}
+
+impl HeaderField {
+    fn is_number(&self) -> bool {
+        matches!(
+            self,
+            HeaderField::RunId
+                | HeaderField::FlowcellLane
+                | HeaderField::TileNumber
+                | HeaderField::X
+                | HeaderField::Y
+        )
+    }
+}
+
+/// Policies for each header field applied by `Header::scramble_with()`.
+///
+/// Policies can be read from a TOML file like
+///
+/// ```toml
+/// flowcell_lane = "keep"
+/// tile_number = "keep"
+/// flowcell_id = "hash"
+/// instrument_name = { constant = "TEST01" }
+/// ```
+///
+/// Missing fields will use the default policy.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScramblePolicy {
+    /// Policy of the instrument name
+    pub instrument_name: FieldPolicy,
+    /// Policy of the run ID
+    pub run_id: FieldPolicy,
+    /// Policy of the flowcell ID
+    pub flowcell_id: FieldPolicy,
+    /// Policy of the flowcell lane
+    pub flowcell_lane: FieldPolicy,
+    /// Policy of the tile number
+    pub tile_number: FieldPolicy,
+    /// Policy of the x coordinate
+    pub x: FieldPolicy,
+    /// Policy of the y coordinate
+    pub y: FieldPolicy,
+    /// Policy of the index sequence
+    pub index_sequence: FieldPolicy,
+    /// Policy of the index number
+    pub index_number: FieldPolicy,
+}
+
+impl Default for ScramblePolicy {
+    fn default() -> Self {
+        ScramblePolicy {
+            instrument_name: FieldPolicy::Scramble,
+            run_id: FieldPolicy::Scramble,
+            flowcell_id: FieldPolicy::Scramble,
+            flowcell_lane: FieldPolicy::Scramble,
+            tile_number: FieldPolicy::Scramble,
+            x: FieldPolicy::Scramble,
+            y: FieldPolicy::Scramble,
+            index_sequence: FieldPolicy::Scramble,
+            index_number: FieldPolicy::Keep,
+        }
+    }
+}
+
+impl ScramblePolicy {
+    /// Reads the policy from a TOML file
+    pub fn read_file(path: PathBuf) -> Result<ScramblePolicy, Error> {
+        let content =
+            fs::read_to_string(path).map_err(|_| Io("Cannot read policy file".to_string()))?;
+        let policy = toml::from_str::<ScramblePolicy>(&content)
+            .map_err(|err| Parse(format!("Invalid policy file: {}", err.message())))?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Sets the policy for given field. Constant values of number fields must be valid numbers.
+    pub fn set(&mut self, field: HeaderField, policy: FieldPolicy) -> Result<(), Error> {
+        if let FieldPolicy::Constant(value) = &policy
+            && field.is_number()
+            && value.parse::<u32>().is_err()
+        {
+            return Err(Parse(format!(
+                "Invalid constant value '{}' for number field '{}'",
+                value,
+                field.to_possible_value().unwrap().get_name()
+            )));
+        }
+
+        let field = match field {
+            HeaderField::InstrumentName => &mut self.instrument_name,
+            HeaderField::RunId => &mut self.run_id,
+            HeaderField::FlowcellId => &mut self.flowcell_id,
+            HeaderField::FlowcellLane => &mut self.flowcell_lane,
+            HeaderField::TileNumber => &mut self.tile_number,
+            HeaderField::X => &mut self.x,
+            HeaderField::Y => &mut self.y,
+            HeaderField::IndexSequence => &mut self.index_sequence,
+            HeaderField::IndexNumber => &mut self.index_number,
+        };
+        *field = policy;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let mut policy = ScramblePolicy::default();
+        policy.set(HeaderField::RunId, self.run_id.clone())?;
+        policy.set(HeaderField::FlowcellLane, self.flowcell_lane.clone())?;
+        policy.set(HeaderField::TileNumber, self.tile_number.clone())?;
+        policy.set(HeaderField::X, self.x.clone())?;
+        policy.set(HeaderField::Y, self.y.clone())
+    }
+}
+
+impl FieldPolicy {
+    /// Applies this policy to a string value using given function to scramble the value
+    pub fn apply_string(&self, value: &str, scramble: impl Fn(&str) -> String) -> String {
+        match self {
+            FieldPolicy::Keep => value.to_string(),
+            FieldPolicy::Scramble => scramble(value),
+            FieldPolicy::Hash => hash(value)
+                .iter()
+                .flat_map(|b| base16ct::upper::encode_string(&[*b]).into_bytes())
+                .take(value.len())
+                .map(|b| b as char)
+                .collect(),
+            FieldPolicy::Constant(constant) => constant.to_string(),
+        }
+    }
+
+    /// Applies this policy to a sequence value using given function to scramble the value.
+    /// Hashed sequences only contain bases and keep `N` and `+` of the original value.
+    pub fn apply_sequence(&self, value: &str, scramble: impl Fn(&str) -> String) -> String {
+        match self {
+            FieldPolicy::Hash => value
+                .chars()
+                .zip(hash(value).iter().cycle())
+                .map(|(c, b)| match c {
+                    'N' | '+' => c,
+                    _ => ['A', 'C', 'G', 'T'][(b % 4) as usize],
+                })
+                .collect(),
+            _ => self.apply_string(value, scramble),
+        }
+    }
+
+    /// Applies this policy to a number value using given function to scramble the value.
+    /// Hashed numbers will not have more digits than the original value.
+    pub fn apply_number(&self, value: u32, scramble: impl Fn(u32) -> u32) -> u32 {
+        match self {
+            FieldPolicy::Keep => value,
+            FieldPolicy::Scramble => scramble(value),
+            FieldPolicy::Hash => {
+                let hash = hash(&value.to_string());
+                let hash = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]);
+                hash % 10u32.saturating_pow(value.to_string().len() as u32)
+            }
+            // Constant values of number fields are checked to be valid numbers
+            FieldPolicy::Constant(constant) => constant.parse().unwrap_or_default(),
+        }
+    }
+}
+
+fn hash(value: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(value.as_bytes());
+    hasher.finalize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fastq::Header;
+    use crate::scramble_policy::{FieldPolicy, HeaderField, ScramblePolicy};
+
+    #[test]
+    fn should_apply_field_policies() {
+        let given = "@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:NGAAGCAA+NATGCTGA";
+
+        let mut policy = ScramblePolicy::default();
+        policy
+            .set(HeaderField::FlowcellLane, FieldPolicy::Keep)
+            .unwrap();
+        policy
+            .set(HeaderField::TileNumber, FieldPolicy::Keep)
+            .unwrap();
+        policy
+            .set(HeaderField::IndexSequence, FieldPolicy::Keep)
+            .unwrap();
+        policy
+            .set(
+                HeaderField::InstrumentName,
+                FieldPolicy::Constant("TEST01".to_string()),
+            )
+            .unwrap();
+        policy.set(HeaderField::RunId, FieldPolicy::Hash).unwrap();
+
+        let actual = given.parse::<Header>().unwrap().scramble_with(&policy);
+
+        assert_eq!(
+            actual.to_string(),
+            "@TEST01:485:CQEAACM:2:2104:15353:197403 1:Y:18:NGAAGCAA+NATGCTGA"
+        );
+    }
+
+    #[test]
+    fn should_read_policy_from_toml() {
+        let given = r#"
+            flowcell_lane = "keep"
+            flowcell_id = "hash"
+            instrument_name = { constant = "TEST01" }
+        "#;
+
+        let actual = toml::from_str::<ScramblePolicy>(given).unwrap();
+
+        assert_eq!(actual.flowcell_lane, FieldPolicy::Keep);
+        assert_eq!(actual.flowcell_id, FieldPolicy::Hash);
+        assert_eq!(
+            actual.instrument_name,
+            FieldPolicy::Constant("TEST01".to_string())
+        );
+        assert_eq!(actual.tile_number, FieldPolicy::Scramble);
+    }
+
+    #[test]
+    fn should_not_accept_invalid_number_constant() {
+        let mut policy = ScramblePolicy::default();
+
+        assert!(
+            policy
+                .set(HeaderField::X, FieldPolicy::Constant("abc".to_string()))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Statistics of FASTQ records
+
+use crate::fastq::{Header, Pair};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Counts of distinct header values and read lengths of FASTQ records.
+///
+/// Memory only grows with the number of distinct values, not with the number of records.
+/// Stats of parts of a file, e.g. processed by different threads, or of different files can
+/// be merged.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    records: usize,
+    instrument_names: HashMap<String, usize>,
+    flowcell_ids: HashMap<String, usize>,
+    flowcell_lanes: HashMap<u32, usize>,
+    read_orders: HashMap<&'static str, usize>,
+    read_lengths: HashMap<usize, usize>,
+}
+
+impl Stats {
+    /// Counts values of a record header. String values are only copied if not seen before.
+    pub fn add_header(&mut self, header: &Header<'_>) {
+        fn count_str(counts: &mut HashMap<String, usize>, value: &str) {
+            match counts.get_mut(value) {
+                Some(count) => *count += 1,
+                None => {
+                    counts.insert(value.to_string(), 1);
+                }
+            }
+        }
+
+        self.records += 1;
+        count_str(&mut self.instrument_names, header.instrument_name());
+        if let Some(flowcell_id) = header.flowcell_id() {
+            count_str(&mut self.flowcell_ids, flowcell_id);
+        }
+        *self
+            .flowcell_lanes
+            .entry(header.flowcell_lane())
+            .or_default() += 1;
+        *self
+            .read_orders
+            .entry(match header.pair_member() {
+                Pair::PairedEnd => "R1",
+                Pair::MatePair => "R2",
+            })
+            .or_default() += 1;
+    }
+
+    /// Counts the read length of a record
+    pub fn add_read_length(&mut self, read_length: usize) {
+        *self.read_lengths.entry(read_length).or_default() += 1;
+    }
+
+    /// Adds all counts of other stats to these stats
+    pub fn merge(&mut self, other: Stats) {
+        fn merge_counts<K: Eq + Hash>(counts: &mut HashMap<K, usize>, other: HashMap<K, usize>) {
+            for (value, count) in other {
+                *counts.entry(value).or_default() += count;
+            }
+        }
+
+        self.records += other.records;
+        merge_counts(&mut self.instrument_names, other.instrument_names);
+        merge_counts(&mut self.flowcell_ids, other.flowcell_ids);
+        merge_counts(&mut self.flowcell_lanes, other.flowcell_lanes);
+        merge_counts(&mut self.read_orders, other.read_orders);
+        merge_counts(&mut self.read_lengths, other.read_lengths);
+    }
+
+    /// Returns the number of records with a valid header
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Returns the number of records of each instrument name
+    pub fn instrument_names(&self) -> &HashMap<String, usize> {
+        &self.instrument_names
+    }
+
+    /// Returns the number of records of each flowcell ID
+    pub fn flowcell_ids(&self) -> &HashMap<String, usize> {
+        &self.flowcell_ids
+    }
+
+    /// Returns the number of records of each flowcell lane
+    pub fn flowcell_lanes(&self) -> &HashMap<u32, usize> {
+        &self.flowcell_lanes
+    }
+
+    /// Returns the number of records of each read order, `R1` or `R2`
+    pub fn read_orders(&self) -> &HashMap<&'static str, usize> {
+        &self.read_orders
+    }
+
+    /// Returns the number of records of each read length
+    pub fn read_lengths(&self) -> &HashMap<usize, usize> {
+        &self.read_lengths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fastq::Header;
+    use crate::stats::Stats;
+
+    #[test]
+    fn should_count_and_merge_values() {
+        let r1 = Header::parse("@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG").unwrap();
+        let r2 = Header::parse("@EAS139:136:FC706VJ:3:2104:15343:197393 2:Y:18:ATCACG").unwrap();
+
+        let mut actual = Stats::default();
+        actual.add_header(&r1);
+        actual.add_read_length(100);
+
+        let mut other = Stats::default();
+        other.add_header(&r1);
+        other.add_header(&r2);
+        other.add_read_length(100);
+        other.add_read_length(99);
+
+        actual.merge(other);
+
+        assert_eq!(actual.records(), 3);
+        assert_eq!(actual.instrument_names().get("EAS139"), Some(&3));
+        assert_eq!(actual.flowcell_ids().get("FC706VJ"), Some(&3));
+        assert_eq!(actual.flowcell_lanes().get(&2), Some(&2));
+        assert_eq!(actual.flowcell_lanes().get(&3), Some(&1));
+        assert_eq!(actual.read_orders().get("R1"), Some(&2));
+        assert_eq!(actual.read_orders().get("R2"), Some(&1));
+        assert_eq!(actual.read_lengths().get(&100), Some(&2));
+        assert_eq!(actual.read_lengths().get(&99), Some(&1));
+    }
+}
@@ -0,0 +1,549 @@
+//! GRZ submissions and their `metadata.json`
+
+use crate::checksum::ChecksumReader;
+use crate::error::Error;
+use crate::error::Error::{Io, Parse, Validation};
+use crate::metadata_file::{ChecksumType, FileType, MetadataFile};
+use crate::walk;
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+
+/// A GRZ submission with the submission root containing the `files/` directory.
+///
+/// Files are expected to be located in `files/<donor pseudonym>/<lab data name>/`,
+/// files in deeper directories are added to the lab data entry of the second directory.
+pub struct Submission {
+    root: PathBuf,
+    long_read: bool,
+    threads: usize,
+}
+
+impl Submission {
+    /// Creates a submission with given submission root
+    pub fn new(root: PathBuf) -> Self {
+        Submission {
+            root,
+            long_read: false,
+            threads: 1,
+        }
+    }
+
+    /// Selects long-read mode for FASTQ files of generated metadata, see
+    /// `MetadataFileBuilder::long_read()`. Validation uses long-read mode for FASTQ files
+    /// without read order in the expected metadata.
+    pub fn long_read(mut self, long_read: bool) -> Self {
+        self.long_read = long_read;
+        self
+    }
+
+    /// Sets the number of threads used to read each file, see `MetadataFileBuilder::threads()`
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Returns the directory containing the files of this submission
+    pub fn files_dir(&self) -> PathBuf {
+        self.root.join("files")
+    }
+
+    /// Returns the path of given file relative to the `files/` directory using `/` as separator.
+    /// Symbolic links and `..` components are resolved, the file must be located in the
+    /// `files/` directory.
+    pub fn relative_path(&self, path: &Path) -> Result<String, Error> {
+        let files_dir = self.files_dir().canonicalize().map_err(|_| {
+            Io(format!(
+                "Invalid submission: Cannot find directory '{}'",
+                self.files_dir().display()
+            ))
+        })?;
+        let path = path
+            .canonicalize()
+            .map_err(|_| Io("Cannot read file".to_string()))?;
+
+        match path.strip_prefix(&files_dir) {
+            Ok(relative_path) if relative_path.components().next().is_some() => Ok(relative_path
+                .iter()
+                .map(|component| component.to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/")),
+            _ => Err(Validation(format!(
+                "File '{}' is not located in the submission files directory",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Returns the complete `metadata.json` content. Submission, donor and lab data fields
+    /// of the template are kept, files of the lab data entries are generated.
+    pub fn metadata(
+        &self,
+        template: Option<Value>,
+        checksum_type: &ChecksumType,
+    ) -> Result<Value, Error> {
+        let mut metadata = match template {
+            Some(Value::Object(template)) => template,
+            Some(_) => {
+                return Err(Parse(
+                    "Invalid submission: Template is not an object".to_string(),
+                ));
+            }
+            None => Map::new(),
+        };
+
+        if !metadata.contains_key("submission") {
+            metadata.insert("submission".to_string(), json!({}));
+        }
+
+        let mut donors = match metadata.remove("donors") {
+            Some(Value::Array(donors)) => donors,
+            Some(_) => {
+                return Err(Parse(
+                    "Invalid submission: Donors is not an array".to_string(),
+                ));
+            }
+            None => vec![],
+        };
+
+        for ((donor_pseudonym, lab_data_name), files) in self.lab_data_files(checksum_type)? {
+            let donor = find_or_insert(&mut donors, "donorPseudonym", &donor_pseudonym);
+            let lab_data = match donor
+                .entry("labData")
+                .or_insert_with(|| json!([]))
+                .as_array_mut()
+            {
+                Some(lab_data) => find_or_insert(lab_data, "labDataName", &lab_data_name),
+                None => {
+                    return Err(Parse(
+                        "Invalid submission: Lab data is not an array".to_string(),
+                    ));
+                }
+            };
+
+            let sequence_data = match lab_data
+                .entry("sequenceData")
+                .or_insert_with(|| json!({}))
+                .as_object_mut()
+            {
+                Some(sequence_data) => sequence_data,
+                None => {
+                    return Err(Parse(
+                        "Invalid submission: Sequence data is not an object".to_string(),
+                    ));
+                }
+            };
+
+            sequence_data.insert(
+                "files".to_string(),
+                serde_json::to_value(files).map_err(|err| Parse(err.to_string()))?,
+            );
+        }
+
+        metadata.insert("donors".to_string(), Value::Array(donors));
+
+        Ok(Value::Object(metadata))
+    }
+
+    /// Validates metadata of all files referenced in `metadata.json` content or in a
+    /// single file metadata object against the actual files and returns all mismatches
+    /// prefixed by the file path.
+    pub fn validate(&self, metadata: Value) -> Result<Vec<String>, Error> {
+        let files = match metadata.get("donors") {
+            Some(donors) => donors
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|donor| donor.get("labData")?.as_array())
+                .flatten()
+                .filter_map(|lab_data| lab_data.get("sequenceData")?.get("files")?.as_array())
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>(),
+            None => vec![metadata],
+        };
+
+        let mut mismatches = vec![];
+        for file in files {
+            let expected = serde_json::from_value::<MetadataFile>(file).map_err(|err| {
+                Parse(format!("Invalid submission: Invalid file metadata: {err}"))
+            })?;
+
+            let relative_path = Path::new(&expected.file_path);
+            if relative_path.is_absolute()
+                || relative_path
+                    .components()
+                    .any(|component| component == Component::ParentDir)
+            {
+                mismatches.push(format!(
+                    "{}: File path is not relative to files directory",
+                    expected.file_path
+                ));
+                continue;
+            }
+
+            let path = self.files_dir().join(relative_path);
+            if !path.is_file() {
+                mismatches.push(format!("{}: File not found", expected.file_path));
+                continue;
+            }
+
+            let checksum_type = expected
+                .checksum_type
+                .as_ref()
+                .unwrap_or(&ChecksumType::Sha256);
+            // Metadata of FASTQ files read in long-read mode does not contain a read order
+            let long_read = expected.file_type == FileType::Fastq && expected.read_order.is_none();
+            let file_mismatches =
+                match MetadataFile::read_file(path.clone(), long_read, checksum_type, self.threads)
+                {
+                    Ok(actual) => expected.mismatches(&actual),
+                    Err(err) => {
+                        // Checksum and size are compared even if the file content is invalid
+                        let mut file_mismatches = vec![err.to_string()];
+                        match File::open(&path).and_then(|file| {
+                            ChecksumReader::with_type(file, checksum_type).finish()
+                        }) {
+                            Ok((file_checksum, file_size_in_bytes)) => file_mismatches.extend(
+                                expected.file_mismatches(&file_checksum, file_size_in_bytes),
+                            ),
+                            Err(_) => file_mismatches.push("Cannot read file".to_string()),
+                        }
+                        file_mismatches
+                    }
+                };
+            mismatches.extend(
+                file_mismatches
+                    .into_iter()
+                    .map(|mismatch| format!("{}: {}", expected.file_path, mismatch)),
+            );
+        }
+
+        Ok(mismatches)
+    }
+
+    /// Returns metadata of all files grouped by donor pseudonym and lab data name
+    fn lab_data_files(
+        &self,
+        checksum_type: &ChecksumType,
+    ) -> Result<BTreeMap<(String, String), Vec<MetadataFile>>, Error> {
+        let files_dir = self.files_dir();
+        let mut result = BTreeMap::<(String, String), Vec<MetadataFile>>::new();
+
+        let files = walk(&files_dir).map_err(|_| {
+            Io(format!(
+                "Invalid submission: Cannot read directory '{}'",
+                files_dir.display()
+            ))
+        })?;
+
+        for path in files {
+            let relative_path = path
+                .strip_prefix(&files_dir)
+                .map_err(|_| Io("Cannot read file".to_string()))?
+                .iter()
+                .map(|component| component.to_string_lossy().to_string())
+                .collect::<Vec<_>>();
+
+            if relative_path.len() < 3 {
+                return Err(Validation(format!(
+                    "Invalid submission: File '{}' is not located in a lab data directory",
+                    relative_path.join("/")
+                )));
+            }
+
+            let mut file_metadata =
+                MetadataFile::read_file(path.clone(), self.long_read, checksum_type, self.threads)
+                    .map_err(|err| err.prefixed(&relative_path.join("/")))?;
+            file_metadata.file_path = relative_path.join("/");
+
+            result
+                .entry((relative_path[0].clone(), relative_path[1].clone()))
+                .or_default()
+                .push(file_metadata);
+        }
+
+        Ok(result)
+    }
+}
+
+/// Returns the object in values with given key and value or inserts a new one
+fn find_or_insert<'a>(
+    values: &'a mut Vec<Value>,
+    key: &str,
+    value: &str,
+) -> &'a mut Map<String, Value> {
+    let idx = match values
+        .iter()
+        .position(|entry| entry.get(key).and_then(|v| v.as_str()) == Some(value))
+    {
+        Some(idx) => idx,
+        None => {
+            values.push(json!({ key: value }));
+            values.len() - 1
+        }
+    };
+
+    values[idx]
+        .as_object_mut()
+        .expect("Entry with key is an object")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error::Validation;
+    use crate::metadata_file::ChecksumType;
+    use crate::submission::Submission;
+    use crate::test_util::{R1_RECORD_1 as R1, R2_RECORD_1 as R2, temp_dir};
+    use serde_json::{Value, json};
+    use std::fs;
+
+    fn files(metadata: &Value) -> Vec<&Value> {
+        metadata["donors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|donor| donor["labData"].as_array().unwrap())
+            .flat_map(|lab_data| lab_data["sequenceData"]["files"].as_array().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn should_group_files_by_donor_and_lab_data() {
+        let root = temp_dir(&[
+            ("files/donor_1/lab_data_1/sample_R1.fastq", R1),
+            ("files/donor_1/lab_data_1/sample_R2.fastq", R2),
+            ("files/donor_1/lab_data_2/run_1/sample_R1.fastq", R1),
+            ("files/donor_2/lab_data_1/sample_R1.fastq", R1),
+        ]);
+
+        let actual = Submission::new(root.path().to_path_buf())
+            .metadata(None, &ChecksumType::Sha256)
+            .unwrap();
+
+        assert_eq!(actual["submission"], json!({}));
+        let donors = actual["donors"].as_array().unwrap();
+        assert_eq!(donors.len(), 2);
+        assert_eq!(donors[0]["donorPseudonym"], "donor_1");
+        assert_eq!(donors[1]["donorPseudonym"], "donor_2");
+
+        let lab_data = donors[0]["labData"].as_array().unwrap();
+        assert_eq!(lab_data.len(), 2);
+        assert_eq!(lab_data[0]["labDataName"], "lab_data_1");
+        assert_eq!(lab_data[1]["labDataName"], "lab_data_2");
+
+        let files = lab_data[0]["sequenceData"]["files"].as_array().unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0]["filePath"], "donor_1/lab_data_1/sample_R1.fastq");
+        assert_eq!(files[0]["readOrder"], "R1");
+        assert_eq!(files[1]["filePath"], "donor_1/lab_data_1/sample_R2.fastq");
+        assert_eq!(files[1]["readOrder"], "R2");
+        assert_eq!(
+            lab_data[1]["sequenceData"]["files"][0]["filePath"],
+            "donor_1/lab_data_2/run_1/sample_R1.fastq"
+        );
+    }
+
+    #[test]
+    fn should_merge_template_fields() {
+        let root = temp_dir(&[
+            ("files/donor_1/lab_data_1/sample_R1.fastq", R1),
+            ("files/donor_2/lab_data_1/sample_R1.fastq", R1),
+        ]);
+        let template = json!({
+            "submission": { "submissionType": "initial" },
+            "donors": [
+                {
+                    "donorPseudonym": "donor_1",
+                    "gender": "female",
+                    "labData": [
+                        {
+                            "labDataName": "lab_data_1",
+                            "sequenceType": "dna",
+                            "sequenceData": { "bioinformaticsPipelineName": "pipeline" }
+                        }
+                    ]
+                },
+                { "donorPseudonym": "donor_3" }
+            ]
+        });
+
+        let actual = Submission::new(root.path().to_path_buf())
+            .metadata(Some(template), &ChecksumType::Sha256)
+            .unwrap();
+
+        assert_eq!(actual["submission"]["submissionType"], "initial");
+        let donors = actual["donors"].as_array().unwrap();
+        assert_eq!(donors.len(), 3);
+        assert_eq!(donors[0]["gender"], "female");
+        assert_eq!(donors[0]["labData"][0]["sequenceType"], "dna");
+        let sequence_data = &donors[0]["labData"][0]["sequenceData"];
+        assert_eq!(sequence_data["bioinformaticsPipelineName"], "pipeline");
+        assert_eq!(sequence_data["files"].as_array().unwrap().len(), 1);
+        assert_eq!(donors[1], json!({ "donorPseudonym": "donor_3" }));
+        assert_eq!(donors[2]["donorPseudonym"], "donor_2");
+    }
+
+    #[test]
+    fn should_reject_files_not_in_lab_data_directory() {
+        let root = temp_dir(&[("files/donor_1/sample_R1.fastq", R1)]);
+
+        let actual =
+            Submission::new(root.path().to_path_buf()).metadata(None, &ChecksumType::Sha256);
+
+        assert_eq!(
+            actual,
+            Err(Validation(
+                "Invalid submission: File 'donor_1/sample_R1.fastq' is not located in a lab data directory"
+                    .to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn should_return_relative_path_of_file_in_files_directory() {
+        let root = temp_dir(&[("files/donor_1/lab_data_1/sample_R1.fastq", R1)]);
+        let submission = Submission::new(root.path().to_path_buf());
+
+        let actual = submission.relative_path(
+            &root
+                .path()
+                .join("files/donor_1/../donor_1/lab_data_1/sample_R1.fastq"),
+        );
+
+        assert_eq!(actual, Ok("donor_1/lab_data_1/sample_R1.fastq".to_string()));
+    }
+
+    #[test]
+    fn should_reject_paths_outside_files_directory() {
+        let root = temp_dir(&[("files/donor_1/lab_data_1/sample_R1.fastq", R1)]);
+        fs::write(root.path().join("outside.fastq"), R1).unwrap();
+        fs::create_dir(root.path().join("files_2")).unwrap();
+        fs::write(root.path().join("files_2/sample_R1.fastq"), R1).unwrap();
+        let submission = Submission::new(root.path().to_path_buf());
+
+        let paths = [
+            root.path().join("files/../outside.fastq"),
+            root.path().join("files/donor_1/../../outside.fastq"),
+            root.path().join("files_2/sample_R1.fastq"),
+            root.path().join("files"),
+            root.path().join("files/donor_1/.."),
+        ];
+
+        for path in paths {
+            assert!(
+                matches!(
+                    submission.relative_path(&path),
+                    Err(Validation(err)) if err.ends_with("is not located in the submission files directory")
+                ),
+                "{} was accepted",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_reject_symbolic_links_to_files_outside_files_directory() {
+        let root = temp_dir(&[("files/donor_1/lab_data_1/sample_R1.fastq", R1)]);
+        fs::write(root.path().join("outside.fastq"), R1).unwrap();
+        std::os::unix::fs::symlink(
+            root.path().join("outside.fastq"),
+            root.path().join("files/donor_1/lab_data_1/sample_R2.fastq"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(root.path(), root.path().join("files/donor_2")).unwrap();
+        let submission = Submission::new(root.path().to_path_buf());
+
+        assert!(matches!(
+            submission.relative_path(&root.path().join("files/donor_1/lab_data_1/sample_R2.fastq")),
+            Err(Validation(_))
+        ));
+        assert!(matches!(
+            submission.relative_path(&root.path().join("files/donor_2/outside.fastq")),
+            Err(Validation(_))
+        ));
+    }
+
+    #[test]
+    fn should_validate_matching_metadata() {
+        let root = temp_dir(&[("files/donor_1/lab_data_1/sample_R1.fastq", R1)]);
+        let submission = Submission::new(root.path().to_path_buf());
+
+        let metadata = submission.metadata(None, &ChecksumType::Md5).unwrap();
+
+        assert_eq!(files(&metadata)[0]["checksumType"], "md5");
+        assert_eq!(submission.validate(metadata).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn should_validate_metadata_of_long_read_files() {
+        let long_reads = [R1, &R1.replace("GATT\n+\nIIII", "GATTACA\n+\nIIIIIII")].concat();
+        let root = temp_dir(&[("files/donor_1/lab_data_1/reads.fastq", long_reads)]);
+
+        let short_read = Submission::new(root.path().to_path_buf());
+        assert!(short_read.metadata(None, &ChecksumType::Sha256).is_err());
+
+        let submission = Submission::new(root.path().to_path_buf()).long_read(true);
+        let metadata = submission.metadata(None, &ChecksumType::Sha256).unwrap();
+
+        assert_eq!(files(&metadata)[0]["readLength"], 6);
+        assert_eq!(files(&metadata)[0].get("readOrder"), None);
+        assert_eq!(short_read.validate(metadata).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn should_report_all_mismatches_of_file_with_invalid_content() {
+        let root = temp_dir(&[("files/donor_1/lab_data_1/sample_R1.fastq", R1)]);
+        let submission = Submission::new(root.path().to_path_buf());
+        let metadata = submission.metadata(None, &ChecksumType::Sha256).unwrap();
+
+        // Incomplete record
+        fs::write(
+            root.path().join("files/donor_1/lab_data_1/sample_R1.fastq"),
+            &R1[..R1.len() - 5],
+        )
+        .unwrap();
+
+        let actual = submission.validate(metadata).unwrap();
+
+        assert_eq!(actual.len(), 3);
+        assert_eq!(
+            actual[0],
+            "donor_1/lab_data_1/sample_R1.fastq: Error reading file: File contains invalid or incomplete sequences"
+        );
+        assert!(actual[1].starts_with("donor_1/lab_data_1/sample_R1.fastq: Checksum differs"));
+        assert_eq!(
+            actual[2],
+            format!(
+                "donor_1/lab_data_1/sample_R1.fastq: File size differs: expected {}, found {}",
+                R1.len(),
+                R1.len() - 5
+            )
+        );
+    }
+
+    #[test]
+    fn should_report_missing_files_and_files_outside_files_directory() {
+        let root = temp_dir(&[("files/donor_1/lab_data_1/sample_R1.fastq", R1)]);
+        let submission = Submission::new(root.path().to_path_buf());
+        let mut metadata = submission.metadata(None, &ChecksumType::Sha256).unwrap();
+
+        let file = metadata["donors"][0]["labData"][0]["sequenceData"]["files"][0].clone();
+        let mut missing = file.clone();
+        missing["filePath"] = "donor_1/lab_data_1/sample_R2.fastq".into();
+        let mut outside = file.clone();
+        outside["filePath"] = "../files/donor_1/lab_data_1/sample_R1.fastq".into();
+        metadata["donors"][0]["labData"][0]["sequenceData"]["files"] =
+            Value::Array(vec![file, missing, outside]);
+
+        assert_eq!(
+            submission.validate(metadata).unwrap(),
+            vec![
+                "donor_1/lab_data_1/sample_R2.fastq: File not found",
+                "../files/donor_1/lab_data_1/sample_R1.fastq: File path is not relative to files directory",
+            ]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Fixtures shared by tests of all modules
+
+use crate::bgzf::BgzfWriter;
+use flate2::Compression;
+use std::fs;
+use std::io::Write;
+use tempfile::TempDir;
+
+pub const R1_RECORD_1: &str =
+    "@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG\nGATT\n+\nIIII\n";
+pub const R1_RECORD_2: &str =
+    "@EAS139:136:FC706VJ:2:2104:15343:197394 1:Y:18:ATCACG\nACAT\n+\nIIII\n";
+pub const R2_RECORD_1: &str =
+    "@EAS139:136:FC706VJ:2:2104:15343:197393 2:Y:18:ATCACG\nAATC\n+\nIIII\n";
+pub const R2_RECORD_2: &str =
+    "@EAS139:136:FC706VJ:2:2104:15343:197394 2:Y:18:ATCACG\nATGT\n+\nIIII\n";
+
+/// Returns a temporary directory containing given files, parent directories are created
+pub fn temp_dir<C: AsRef<[u8]>>(files: &[(&str, C)]) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (path, content) in files {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+/// Returns BGZF compressed BAM content of unaligned records given by read name, flag and sequence
+pub fn bam(header: &str, records: &[(&str, u16, &str)]) -> Vec<u8> {
+    let mut data = b"BAM\x01".to_vec();
+    data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    for (read_name, flag, sequence) in records {
+        let mut record = vec![];
+        record.extend_from_slice(&(-1i32).to_le_bytes());
+        record.extend_from_slice(&(-1i32).to_le_bytes());
+        record.push(read_name.len() as u8 + 1);
+        record.extend_from_slice(&[0, 0x48, 0x12, 0, 0]);
+        record.extend_from_slice(&flag.to_le_bytes());
+        record.extend_from_slice(&(sequence.len() as u32).to_le_bytes());
+        record.extend_from_slice(&(-1i32).to_le_bytes());
+        record.extend_from_slice(&(-1i32).to_le_bytes());
+        record.extend_from_slice(&0i32.to_le_bytes());
+        record.extend_from_slice(read_name.as_bytes());
+        record.push(0);
+        record.extend(sequence.as_bytes().chunks(2).map(|bases| {
+            let code =
+                |base: &u8| b"=ACMGRSVTWYHKDBN".iter().position(|b| b == base).unwrap() as u8;
+            (code(&bases[0]) << 4) | bases.get(1).map(code).unwrap_or(0)
+        }));
+        record.extend(std::iter::repeat_n(30, sequence.len()));
+        data.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        data.extend(record);
+    }
+
+    let mut writer = BgzfWriter::new(vec![], Compression::default(), 1);
+    writer.write_all(&data).unwrap();
+    writer.finish().unwrap()
+}
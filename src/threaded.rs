@@ -0,0 +1,396 @@
+//! Reading, processing and writing data using multiple threads
+
+use crate::compression::CompressedWriter;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{BufRead, ErrorKind, Read, Write};
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, SyncSender, channel, sync_channel};
+use std::thread;
+use std::thread::JoinHandle;
+
+/// Size of data chunks passed between threads
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Number of chunks buffered between threads
+const CHANNEL_SIZE: usize = 4;
+
+/// Default number of items processed by a worker thread at once
+pub const BATCH_SIZE: usize = 1024;
+
+/// Number of batches per worker thread read ahead of the output
+const BATCHES_PER_THREAD: usize = 2;
+
+/// Reader running the given reader, e.g. a decompressing reader, on a separate thread.
+///
+/// Data is read in chunks ahead of the consuming thread, so decompression and processing
+/// of data run in parallel.
+pub struct ThreadedReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl ThreadedReader {
+    /// Creates the reader using `open` on the reading thread
+    pub fn new<R: Read>(open: impl FnOnce() -> io::Result<R> + Send + 'static) -> Self {
+        let (sender, receiver) = sync_channel(CHANNEL_SIZE);
+
+        thread::spawn(move || {
+            let mut reader = match open() {
+                Ok(reader) => reader,
+                Err(err) => {
+                    let _ = sender.send(Err(err));
+                    return;
+                }
+            };
+
+            loop {
+                let mut chunk = vec![0; CHUNK_SIZE];
+                let result = match reader.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        chunk.truncate(n);
+                        Ok(chunk)
+                    }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(err) => Err(err),
+                };
+                let failed = result.is_err();
+                // Stop reading if the consuming side has been dropped
+                if sender.send(result).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        ThreadedReader {
+            receiver,
+            buf: vec![],
+            pos: 0,
+        }
+    }
+}
+
+impl Read for ThreadedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for ThreadedReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.buf.len() {
+            match self.receiver.recv() {
+                Ok(Ok(chunk)) => {
+                    self.buf = chunk;
+                    self.pos = 0;
+                }
+                Ok(Err(err)) => return Err(err),
+                // Reading thread has finished
+                Err(_) => {
+                    self.buf.clear();
+                    self.pos = 0;
+                }
+            }
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+/// Writer passing data to a separate thread running the given writer, e.g. a compressing
+/// writer, so processing and compression of data run in parallel.
+///
+/// Call `finish()` to write remaining data and wait for the writing thread.
+pub struct ThreadedWriter {
+    sender: Option<SyncSender<Vec<u8>>>,
+    buf: Vec<u8>,
+    handle: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl ThreadedWriter {
+    /// Creates the writer calling `finish` on the writing thread after all data was written
+    pub fn new<W: Write + Send + 'static>(
+        mut inner: W,
+        finish: impl FnOnce(W) -> io::Result<()> + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = sync_channel::<Vec<u8>>(CHANNEL_SIZE);
+
+        let handle = thread::spawn(move || {
+            for chunk in receiver {
+                inner.write_all(&chunk)?;
+            }
+            finish(inner)
+        });
+
+        ThreadedWriter {
+            sender: Some(sender),
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            handle: Some(handle),
+        }
+    }
+
+    /// Writes remaining data and returns the result of the writing thread
+    pub fn finish(mut self) -> io::Result<()> {
+        self.send()?;
+        self.sender = None;
+        self.join()
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        let sent = match &self.sender {
+            Some(sender) => sender.send(chunk).is_ok(),
+            None => false,
+        };
+
+        if sent {
+            Ok(())
+        } else {
+            // Writing thread has stopped, return its error
+            self.sender = None;
+            self.join()?;
+            Err(io::Error::new(ErrorKind::BrokenPipe, "Cannot write output"))
+        }
+    }
+
+    fn join(&mut self) -> io::Result<()> {
+        match self.handle.take() {
+            Some(handle) => handle
+                .join()
+                .map_err(|_| io::Error::other("Cannot write output"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for ThreadedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Passes buffered data to the writing thread
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+impl Drop for ThreadedWriter {
+    fn drop(&mut self) {
+        self.sender = None;
+        let _ = self.join();
+    }
+}
+
+/// Output writer compressing data on the current thread or on a separate thread
+pub enum OutputWriter<W: Write + Send + 'static> {
+    /// Compressing data on the current thread
+    Direct(CompressedWriter<W>),
+    /// Compressing data on a separate thread
+    Threaded(ThreadedWriter),
+}
+
+impl<W: Write + Send + 'static> OutputWriter<W> {
+    /// Returns a writer compressing data on a separate thread if more than one thread is used
+    pub fn new(inner: CompressedWriter<W>, threads: usize) -> Self {
+        if threads > 1 {
+            OutputWriter::Threaded(ThreadedWriter::new(inner, CompressedWriter::finish))
+        } else {
+            OutputWriter::Direct(inner)
+        }
+    }
+
+    /// Writes remaining data and finishes compression
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            OutputWriter::Direct(writer) => writer.finish(),
+            OutputWriter::Threaded(writer) => writer.finish(),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> Write for OutputWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Direct(writer) => writer.write(buf),
+            OutputWriter::Threaded(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Direct(writer) => writer.flush(),
+            OutputWriter::Threaded(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Processes items using a pool of worker threads and passes the results to `output` in
+/// the original order of items.
+///
+/// Items are passed to worker threads in batches of given size, so the processing of each
+/// batch runs in parallel to reading items and writing results. Stops at the first error of
+/// `output`.
+pub fn process_ordered<T: Send, U: Send, E>(
+    items: impl Iterator<Item = T>,
+    batch_size: usize,
+    threads: usize,
+    process: impl Fn(T) -> U + Sync,
+    mut output: impl FnMut(U) -> Result<(), E>,
+) -> Result<(), E> {
+    if threads <= 1 {
+        return items.map(process).try_for_each(output);
+    }
+
+    let (batch_sender, batch_receiver) = sync_channel::<(usize, Vec<T>)>(threads);
+    let (result_sender, result_receiver) = channel::<(usize, thread::Result<Vec<U>>)>();
+    let batch_receiver = Mutex::new(batch_receiver);
+    let (batch_receiver, process) = (&batch_receiver, &process);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let result_sender = result_sender.clone();
+            scope.spawn(move || {
+                loop {
+                    // Release the lock before processing the batch
+                    let batch = batch_receiver.lock().map(|receiver| receiver.recv());
+                    let Ok(Ok((idx, batch))) = batch else {
+                        break;
+                    };
+                    let results = panic::catch_unwind(AssertUnwindSafe(|| {
+                        batch.into_iter().map(process).collect()
+                    }));
+                    if result_sender.send((idx, results)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(result_sender);
+
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        let mut receive = |pending: &mut BTreeMap<usize, Vec<U>>, next: &mut usize| {
+            match result_receiver.recv() {
+                Ok((idx, Ok(results))) => {
+                    pending.insert(idx, results);
+                }
+                // Do not wait for results of a failed worker thread
+                Ok((_, Err(err))) => panic::resume_unwind(err),
+                Err(_) => {}
+            }
+            while let Some(results) = pending.remove(next) {
+                *next += 1;
+                results.into_iter().try_for_each(&mut output)?;
+            }
+            Ok(())
+        };
+
+        let mut items = items.peekable();
+        let mut sent = 0;
+        let result = (|| {
+            while items.peek().is_some() {
+                let batch = items.by_ref().take(batch_size).collect();
+                // Workers only stop if the batch channel was closed
+                let _ = batch_sender.send((sent, batch));
+                sent += 1;
+                while sent - next >= threads * BATCHES_PER_THREAD {
+                    receive(&mut pending, &mut next)?;
+                }
+            }
+            while next < sent {
+                receive(&mut pending, &mut next)?;
+            }
+            Ok(())
+        })();
+
+        // Stops worker threads waiting for batches
+        drop(batch_sender);
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::threaded::{ThreadedReader, ThreadedWriter, process_ordered};
+    use std::io::{BufRead, Write};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn should_read_and_write_on_separate_threads() {
+        let given = (0..100_000)
+            .map(|idx| format!("line {idx}\n"))
+            .collect::<String>();
+
+        let content = given.clone();
+        let reader = ThreadedReader::new(move || Ok(std::io::Cursor::new(content)));
+
+        let actual = Arc::new(Mutex::new(vec![]));
+        let output = actual.clone();
+        let mut writer = ThreadedWriter::new(vec![], move |data| {
+            *output.lock().unwrap() = data;
+            Ok(())
+        });
+
+        for line in reader.lines() {
+            writeln!(writer, "{}", line.unwrap()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(actual.lock().unwrap().clone()).unwrap(),
+            given
+        );
+    }
+
+    #[test]
+    fn should_process_items_in_parallel_keeping_order() {
+        let mut actual = vec![];
+        let result = process_ordered(
+            0..10_000,
+            100,
+            4,
+            |value| value * 2,
+            |value| {
+                actual.push(value);
+                Ok::<(), String>(())
+            },
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(
+            actual,
+            (0..10_000).map(|value| value * 2).collect::<Vec<_>>()
+        );
+
+        let result = process_ordered(
+            0..10_000,
+            100,
+            4,
+            |value| value,
+            |value| match value {
+                5000 => Err(value),
+                _ => Ok(()),
+            },
+        );
+        assert_eq!(result, Err(5000));
+    }
+}
@@ -0,0 +1,499 @@
+//! Scrambling of VCF files
+
+use crate::error::Error;
+use crate::error::Error::{Io, Parse, Validation};
+use crate::reference::{Random, Reference};
+use std::collections::HashMap;
+use std::io::{BufRead, ErrorKind, Write};
+
+/// Keys of meta information lines kept in scrambled VCF files
+const KEPT_META_KEYS: [&str; 6] = ["fileformat", "INFO", "FORMAT", "FILTER", "ALT", "contig"];
+
+/// Keys of structural INFO fields kept in scrambled VCF files. Other fields like `CSQ`, `ANN`
+/// or `HGVS` annotations may reveal original variants and are removed.
+const KEPT_INFO_KEYS: [&str; 6] = ["END", "SVTYPE", "SVLEN", "CIPOS", "CIEND", "IMPRECISE"];
+
+const BASES: [char; 4] = ['A', 'C', 'G', 'T'];
+
+/// Scrambles VCF files by renaming samples, perturbing positions, replacing alleles and
+/// removing identifying header lines.
+///
+/// Positions are moved within given window, keeping records sorted. If a reference is given,
+/// REF alleles are taken from the reference at the new position, otherwise random bases are
+/// used. ALT alleles keep the type of variant (SNV, MNV, insertion or deletion) and symbolic
+/// alleles are kept. Records that do not fit into the contig behind previous records cannot
+/// be scrambled.
+pub struct VcfScrambler<'a> {
+    reference: Option<&'a Reference>,
+    window: u64,
+    contig_lens: HashMap<String, u64>,
+    last_positions: HashMap<String, u64>,
+}
+
+impl<'a> VcfScrambler<'a> {
+    /// Creates a scrambler moving positions within given window, using bases of the
+    /// reference for REF alleles if given
+    pub fn new(reference: Option<&'a Reference>, window: u64) -> Self {
+        VcfScrambler {
+            reference,
+            window,
+            contig_lens: HashMap::new(),
+            last_positions: HashMap::new(),
+        }
+    }
+
+    /// Returns scrambled meta information line or `None` if the line should be removed
+    fn meta_line(&mut self, line: &str) -> Option<String> {
+        let (key, value) = line.strip_prefix("##")?.split_once('=')?;
+
+        if !KEPT_META_KEYS.contains(&key) {
+            return None;
+        }
+
+        if key == "INFO" {
+            let id = value
+                .trim_start_matches('<')
+                .split(',')
+                .find_map(|attribute| attribute.strip_prefix("ID="))?;
+            return match KEPT_INFO_KEYS.contains(&id) {
+                true => Some(line.to_string()),
+                false => None,
+            };
+        }
+
+        if key != "contig" {
+            return Some(line.to_string());
+        }
+
+        // Keep ID and length of contigs only
+        let attributes = value
+            .trim_start_matches('<')
+            .trim_end_matches('>')
+            .split(',')
+            .filter_map(|attribute| attribute.split_once('='))
+            .collect::<HashMap<_, _>>();
+
+        let id = attributes.get("ID")?;
+        match attributes.get("length") {
+            Some(length) => {
+                if let Ok(length) = length.parse() {
+                    self.contig_lens.insert(id.to_string(), length);
+                }
+                Some(format!("##contig=<ID={id},length={length}>"))
+            }
+            None => Some(format!("##contig=<ID={id}>")),
+        }
+    }
+
+    /// Returns header line with samples renamed to `SAMPLE1`, `SAMPLE2`, ...
+    fn header_line(&self, line: &str) -> String {
+        line.split('\t')
+            .enumerate()
+            .map(|(idx, column)| match idx {
+                0..9 => column.to_string(),
+                idx => format!("SAMPLE{}", idx - 8),
+            })
+            .collect::<Vec<_>>()
+            .join("\t")
+    }
+
+    /// Returns the scrambled record, an error if the record cannot be scrambled or `None` if
+    /// the record is invalid
+    fn record(&mut self, line: &str) -> Option<Result<String, Error>> {
+        let mut fields = line
+            .split('\t')
+            .map(|field| field.to_string())
+            .collect::<Vec<_>>();
+        if fields.len() < 8 {
+            return None;
+        }
+
+        let chrom = fields[0].clone();
+        let pos = fields[1].parse::<u64>().ok()?;
+        let reference_allele = fields[3].clone();
+
+        let seed = chrom.bytes().map(|b| b as u64).sum::<u64>() ^ pos.wrapping_mul(31);
+        let mut random = Random::new(seed);
+
+        let contig_len = self
+            .reference
+            .and_then(|reference| reference.contig_len(&chrom).map(|len| len as u64))
+            .or_else(|| self.contig_lens.get(&chrom).copied());
+        let max_pos = contig_len
+            .map(|len| {
+                (len + 1)
+                    .saturating_sub(reference_allele.len() as u64)
+                    .max(1)
+            })
+            .unwrap_or(u64::MAX);
+
+        let offset = random.next() % (2 * self.window + 1);
+        let new_pos = pos
+            .saturating_add(offset)
+            .saturating_sub(self.window)
+            .max(self.last_positions.get(&chrom).copied().unwrap_or(1))
+            .min(max_pos);
+        if new_pos < self.last_positions.get(&chrom).copied().unwrap_or(1) {
+            return Some(Err(Validation(format!(
+                "Record does not fit into contig '{}' behind previous records",
+                chrom
+            ))));
+        }
+        self.last_positions.insert(chrom.clone(), new_pos);
+
+        let new_reference_allele = match self.reference.and_then(|reference| {
+            reference.bases(&chrom, new_pos as usize - 1, reference_allele.len())
+        }) {
+            Some(bases) => String::from_utf8_lossy(bases).to_uppercase(),
+            None => random_bases(reference_allele.len(), &mut random),
+        };
+
+        let mut alternate_alleles = vec![];
+        for allele in fields[4].split(',') {
+            let allele = alternate_allele(
+                &reference_allele,
+                &new_reference_allele,
+                allele,
+                &alternate_alleles,
+                &mut random,
+            );
+            alternate_alleles.push(allele);
+        }
+
+        let info = fields[7]
+            .split(';')
+            .filter(|entry| {
+                let key = entry.split_once('=').map_or(*entry, |(key, _)| key);
+                KEPT_INFO_KEYS.contains(&key)
+            })
+            .map(
+                |entry| match entry.strip_prefix("END=").map(|end| end.parse::<u64>()) {
+                    Some(Ok(end)) => {
+                        format!("END={}", end.saturating_add(new_pos).saturating_sub(pos))
+                    }
+                    _ => entry.to_string(),
+                },
+            )
+            .collect::<Vec<_>>();
+        let info = match info.is_empty() {
+            true => ".".to_string(),
+            false => info.join(";"),
+        };
+
+        fields[1] = new_pos.to_string();
+        fields[2] = ".".to_string();
+        fields[3] = new_reference_allele;
+        fields[4] = alternate_alleles.join(",");
+        fields[7] = info;
+
+        Some(Ok(fields.join("\t")))
+    }
+}
+
+/// Returns alternate allele of same variant type for the new reference allele. SNVs and MNVs
+/// differ from the alternate alleles already created for the record.
+fn alternate_allele(
+    reference_allele: &str,
+    new_reference_allele: &str,
+    allele: &str,
+    alleles: &[String],
+    random: &mut Random,
+) -> String {
+    if allele.starts_with('<') || allele.contains(['[', ']']) || allele == "*" || allele == "." {
+        return allele.to_string();
+    }
+
+    if allele.len() == reference_allele.len() {
+        // SNV or MNV: change bases that differ from reference allele
+        let new_bases = new_reference_allele
+            .chars()
+            .map(|base| base.to_ascii_uppercase())
+            .collect::<Vec<_>>();
+        let mut bases = vec![];
+        let mut last_changed = None;
+        for ((alt, old), new) in allele.chars().zip(reference_allele.chars()).zip(&new_bases) {
+            if alt == old {
+                bases.push(*new);
+            } else {
+                last_changed = Some(bases.len());
+                bases.push(random_base(&[*new], random));
+            }
+        }
+
+        // Alternate alleles of a record must be distinct, so the last changed base is
+        // replaced if the allele was already created
+        let with_base = |bases: &[char], idx: usize, base: char| {
+            bases
+                .iter()
+                .enumerate()
+                .map(|(i, b)| if i == idx { base } else { *b })
+                .collect::<String>()
+        };
+        if let Some(idx) = last_changed
+            && alleles.contains(&bases.iter().collect())
+        {
+            let used = BASES
+                .into_iter()
+                .filter(|base| alleles.contains(&with_base(&bases, idx, *base)))
+                .chain([new_bases[idx]])
+                .collect::<Vec<_>>();
+            if used.len() < BASES.len() {
+                bases[idx] = random_base(&used, random);
+            }
+        }
+        bases.into_iter().collect()
+    } else if allele.len() > reference_allele.len() {
+        // Insertion
+        format!(
+            "{}{}",
+            new_reference_allele,
+            random_bases(allele.len() - reference_allele.len(), random)
+        )
+    } else {
+        // Deletion
+        new_reference_allele[..allele.len()].to_string()
+    }
+}
+
+/// Returns a random base except the given bases
+fn random_base(except: &[char], random: &mut Random) -> char {
+    let bases = BASES
+        .into_iter()
+        .filter(|base| !except.contains(base))
+        .collect::<Vec<_>>();
+    bases[(random.next() % bases.len() as u64) as usize]
+}
+
+fn random_bases(len: usize, random: &mut Random) -> String {
+    (0..len)
+        .map(|_| BASES[(random.next() % 4) as usize])
+        .collect()
+}
+
+/// Scrambles VCF input.
+///
+/// Records that cannot be scrambled are omitted and passed to `on_failure` with the line
+/// number and the error. This will result in an error after all records have been processed.
+pub fn scramble_vcf(
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    reference: Option<&Reference>,
+    window: u64,
+    mut on_failure: impl FnMut(usize, &Error),
+) -> Result<(), Error> {
+    let mut scrambler = VcfScrambler::new(reference, window);
+    let mut buf = String::new();
+    let mut failed = 0;
+    let mut first_error = None;
+
+    let mut line = 1;
+    loop {
+        let n = reader.read_line(&mut buf).map_err(|err| match err.kind() {
+            ErrorKind::InvalidData => Parse(format!("Invalid UTF-8 in VCF input at line {}", line)),
+            _ => Io(format!("Cannot read VCF input at line {}", line)),
+        })?;
+        if n == 0 {
+            break;
+        }
+
+        let value = buf.trim_end_matches(['\n', '\r']);
+
+        let scrambled = if value.starts_with("##") {
+            scrambler.meta_line(value)
+        } else if value.starts_with("#CHROM") {
+            Some(scrambler.header_line(value))
+        } else if value.is_empty() {
+            None
+        } else {
+            match scrambler.record(value) {
+                Some(Ok(record)) => Some(record),
+                Some(Err(err)) => {
+                    failed += 1;
+                    on_failure(line, &err);
+                    first_error.get_or_insert(err);
+                    None
+                }
+                None => return Err(Parse(format!("Invalid VCF record at line {}", line))),
+            }
+        };
+
+        if let Some(scrambled) = scrambled {
+            writeln!(writer, "{scrambled}").map_err(|_| Io("Cannot write output".to_string()))?;
+        }
+
+        line += 1;
+        buf.clear();
+    }
+
+    writer
+        .flush()
+        .map_err(|_| Io("Cannot write output".to_string()))?;
+
+    if let Some(err) = first_error {
+        return Err(err.with_message(format!("{failed} record(s) could not be scrambled")));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error::{Parse, Validation};
+    use crate::reference::Reference;
+    use crate::vcf::scramble_vcf;
+
+    const VCF: &str = "##fileformat=VCFv4.2\n\
+        ##fileDate=20240101\n\
+        ##source=secret_pipeline\n\
+        ##contig=<ID=chrT,length=38,md5=abc>\n\
+        ##INFO=<ID=END,Number=1,Type=Integer,Description=\"End position\">\n\
+        ##INFO=<ID=CSQ,Number=.,Type=String,Description=\"Consequence annotations\">\n\
+        ##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n\
+        #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tpatient_001\tpatient_002\n\
+        chrT\t5\trs123\tA\tG\t50\tPASS\t.\tGT\t0/1\t1/1\n\
+        chrT\t10\t.\tTA\tT\t50\tPASS\t.\tGT\t0/1\t0/0\n\
+        chrT\t20\t.\tC\tCGG,<DEL>\t50\tPASS\tSVTYPE=DEL;END=20;CSQ=G|missense|GENE1|c.20C>G\tGT\t1/2\t0/0\n";
+
+    #[test]
+    fn should_scramble_vcf_using_reference() {
+        let reference =
+            Reference::read(">chrT\nACGTACGGTTAACCGGTTCA\nGGCATTACGATCGATCGA\n".as_bytes())
+                .unwrap();
+
+        let mut actual = vec![];
+        scramble_vcf(VCF.as_bytes(), &mut actual, Some(&reference), 3, |_, _| {}).unwrap();
+        let actual = String::from_utf8(actual).unwrap();
+        let lines = actual.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "##fileformat=VCFv4.2");
+        assert_eq!(lines[1], "##contig=<ID=chrT,length=38>");
+        assert_eq!(
+            lines[4],
+            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSAMPLE1\tSAMPLE2"
+        );
+
+        let mut last_pos = 0;
+        for record in &lines[5..] {
+            let fields = record.split('\t').collect::<Vec<_>>();
+            let pos = fields[1].parse::<usize>().unwrap();
+            assert!(pos >= last_pos && (2..=23).contains(&pos));
+            assert_eq!(fields[2], ".");
+            assert_eq!(
+                fields[3].as_bytes(),
+                reference.bases("chrT", pos - 1, fields[3].len()).unwrap()
+            );
+            assert!(fields[4].split(',').all(|allele| allele != fields[3]));
+            last_pos = pos;
+        }
+
+        let fields = lines[7].split('\t').collect::<Vec<_>>();
+        assert_eq!(
+            fields[7],
+            format!("SVTYPE=DEL;END={}", fields[1].parse::<usize>().unwrap())
+        );
+        assert!(!actual.contains("CSQ"));
+    }
+
+    #[test]
+    fn should_keep_alternate_alleles_of_multi_allelic_records_distinct() {
+        let given = "##fileformat=VCFv4.2\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n"
+            .to_string()
+            + &(1..=50)
+                .map(|pos| format!("chrT\t{pos}\t.\tA\tC,G,T\t50\tPASS\t.\n"))
+                .collect::<String>()
+            + &(1..=50)
+                .map(|pos| format!("chrU\t{pos}\t.\tAC\tCC,GC,CA\t50\tPASS\t.\n"))
+                .collect::<String>();
+
+        let mut actual = vec![];
+        scramble_vcf(given.as_bytes(), &mut actual, None, 3, |_, _| {}).unwrap();
+        let actual = String::from_utf8(actual).unwrap();
+
+        for record in actual.lines().skip(2) {
+            let fields = record.split('\t').collect::<Vec<_>>();
+            let mut alleles = fields[4].split(',').collect::<Vec<_>>();
+            assert!(alleles.iter().all(|allele| *allele != fields[3]));
+            alleles.sort();
+            alleles.dedup();
+            assert_eq!(alleles.len(), 3, "{record}");
+        }
+    }
+
+    #[test]
+    fn should_not_overflow_positions_near_maximum() {
+        let given = format!(
+            "##fileformat=VCFv4.2\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+            chrT\t{}\t.\tA\tG\t50\tPASS\tEND={}\n",
+            u64::MAX - 1,
+            u64::MAX
+        );
+
+        let mut actual = vec![];
+        scramble_vcf(given.as_bytes(), &mut actual, None, 1000, |_, _| {}).unwrap();
+
+        assert_eq!(String::from_utf8(actual).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn should_not_scramble_vcf_with_invalid_utf8() {
+        let given = b"##fileformat=VCFv4.2\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+            chrT\t5\t.\tA\tG\t50\tPASS\tGENE=\xff\n";
+
+        let mut actual = vec![];
+        let result = scramble_vcf(given.as_slice(), &mut actual, None, 3, |_, _| {});
+
+        assert_eq!(
+            result,
+            Err(Parse("Invalid UTF-8 in VCF input at line 3".to_string()))
+        );
+    }
+
+    #[test]
+    fn should_pass_records_that_do_not_fit_into_contig_to_caller() {
+        let reference = Reference::read(">chrT\nACGTACGTAC\n".as_bytes()).unwrap();
+        let given = "##fileformat=VCFv4.2\n\
+            #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+            chrT\t9\t.\tA\tG\t50\tPASS\t.\n\
+            chrT\t10\t.\tACGTAC\tA\t50\tPASS\t.\n\
+            chrT\t10\t.\tC\tT\t50\tPASS\t.\n";
+
+        let mut failures = vec![];
+        let mut actual = vec![];
+        let result = scramble_vcf(
+            given.as_bytes(),
+            &mut actual,
+            Some(&reference),
+            3,
+            |line, err| failures.push((line, err.to_string())),
+        );
+
+        assert_eq!(
+            result,
+            Err(Validation("1 record(s) could not be scrambled".to_string()))
+        );
+        assert_eq!(
+            failures,
+            vec![(
+                4,
+                "Record does not fit into contig 'chrT' behind previous records".to_string()
+            )]
+        );
+
+        let actual = String::from_utf8(actual).unwrap();
+        let records = actual.lines().skip(2).collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        for record in records {
+            let fields = record.split('\t').collect::<Vec<_>>();
+            let pos = fields[1].parse::<usize>().unwrap();
+            assert!(pos <= 10);
+            assert_eq!(
+                fields[3].as_bytes(),
+                reference.bases("chrT", pos - 1, 1).unwrap()
+            );
+        }
+    }
+}
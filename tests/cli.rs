@@ -0,0 +1,264 @@
+use fastq_tools::fastq::{Header, RecordReader};
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::process::Command;
+
+const R1: &str = "@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG\nGATT\n+\nIIII\n";
+const R2: &str = "@EAS139:136:FC706VJ:2:2104:15343:197393 2:Y:18:ATCACG\nAATC\n+\nIIII\n";
+
+fn fastq_tools() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_fastq-tools"))
+}
+
+#[test]
+fn should_scramble_paired_fastq_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let r1 = [R1, &R1.replace("197393", "197394")].concat();
+    let r2 = [R2, &R2.replace("197393", "197394")].concat();
+    fs::write(dir.path().join("R1.fastq"), &r1).unwrap();
+    fs::write(dir.path().join("R2.fastq"), &r2).unwrap();
+
+    let status = fastq_tools()
+        .current_dir(dir.path())
+        .args(["scramble", "-1", "R1.fastq", "-2", "R2.fastq"])
+        .args(["--output1", "out_R1.fastq", "--output2", "out_R2.fastq"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0));
+
+    let headers = |path: &str| {
+        RecordReader::new(BufReader::new(File::open(dir.path().join(path)).unwrap()))
+            .map(|record| record.unwrap().header().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    let (given_1, given_2) = (headers("R1.fastq"), headers("R2.fastq"));
+    let (actual_1, actual_2) = (headers("out_R1.fastq"), headers("out_R2.fastq"));
+
+    assert_eq!(actual_1.len(), given_1.len());
+    assert_eq!(actual_2.len(), given_2.len());
+    for (actual, given) in actual_1
+        .iter()
+        .chain(&actual_2)
+        .zip(given_1.iter().chain(&given_2))
+    {
+        assert_ne!(actual, given);
+    }
+    for (header_1, header_2) in actual_1.iter().zip(&actual_2) {
+        let header_1 = header_1.parse::<Header>().unwrap();
+        let header_2 = header_2.parse::<Header>().unwrap();
+        assert!(header_1.is_mate_of(&header_2));
+    }
+}
+
+#[test]
+fn should_reject_paired_input_of_other_formats() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("R1.fastq"), R1).unwrap();
+    fs::write(dir.path().join("R2.fastq"), R2).unwrap();
+
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args([
+            "scramble", "--format", "sam", "-1", "R1.fastq", "-2", "R2.fastq",
+        ])
+        .args(["--output1", "out_R1.fastq", "--output2", "out_R2.fastq"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(5));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("Paired input is only supported for FASTQ input")
+    );
+    assert!(!dir.path().join("out_R1.fastq").exists());
+    assert!(!dir.path().join("out_R2.fastq").exists());
+}
+
+#[test]
+fn should_exit_with_validation_failure_if_reads_are_not_mates() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("R1.fastq"), R1).unwrap();
+    fs::write(
+        dir.path().join("R2.fastq"),
+        R2.replace("15343:197393", "15343:197394"),
+    )
+    .unwrap();
+
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args(["scramble", "-1", "R1.fastq", "-2", "R2.fastq"])
+        .args(["--output1", "out_R1.fastq", "--output2", "out_R2.fastq"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Reads are not mates"));
+}
+
+#[test]
+fn should_show_info_of_records_with_quality_starting_with_at_sign() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("R1.fastq"),
+        "@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG\nGATT\n+\n@III\n\
+        @EAS139:136:FC706VJ:2:2104:15343:197394 1:Y:18:ATCACG\nGATT\n+\n+III\n",
+    )
+    .unwrap();
+
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args(["--input", "R1.fastq", "info"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Found 2 complete sequence sets"));
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn should_validate_submission_using_multiple_threads() {
+    let dir = tempfile::tempdir().unwrap();
+    let lab_data_dir = dir.path().join("files").join("donor").join("lab_data");
+    fs::create_dir_all(&lab_data_dir).unwrap();
+    fs::create_dir_all(dir.path().join("metadata")).unwrap();
+    fs::write(lab_data_dir.join("sample_R1.fastq"), R1).unwrap();
+    fs::write(lab_data_dir.join("sample_R2.fastq"), R2).unwrap();
+
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args(["-t", "2", "grz-submission", "--submission-root", "."])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    fs::write(
+        dir.path().join("metadata").join("metadata.json"),
+        output.stdout,
+    )
+    .unwrap();
+
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args([
+            "--input",
+            "metadata/metadata.json",
+            "-t",
+            "2",
+            "grz-validate",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Metadata matches all files"));
+
+    fs::write(lab_data_dir.join("sample_R2.fastq"), R1).unwrap();
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args([
+            "--input",
+            "metadata/metadata.json",
+            "-t",
+            "2",
+            "grz-validate",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("sample_R2.fastq: Checksum differs"));
+}
+
+#[test]
+fn should_create_and_verify_checksum_manifest_using_multiple_threads() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("files")).unwrap();
+    fs::write(dir.path().join("files").join("R1.fastq"), R1).unwrap();
+    fs::write(dir.path().join("files").join("R2.fastq"), R2).unwrap();
+
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args(["-t", "2", "checksum", "--dir", "files"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let manifest = String::from_utf8_lossy(&output.stdout).to_string();
+    assert_eq!(
+        manifest.lines().map(|line| &line[66..]).collect::<Vec<_>>(),
+        ["R1.fastq", "R2.fastq"]
+    );
+    fs::write(dir.path().join("files.sha256"), manifest).unwrap();
+
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args([
+            "-t",
+            "2",
+            "checksum",
+            "--verify",
+            "files.sha256",
+            "--dir",
+            "files",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "R1.fastq: OK\nR2.fastq: OK\n"
+    );
+}
+
+#[test]
+fn should_exit_with_error_code_of_category_for_missing_or_multiple_input_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("R1.fastq"), R1).unwrap();
+    fs::write(dir.path().join("R2.fastq"), R2).unwrap();
+
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args(["--input", "R1.fastq", "--input", "R2.fastq", "scramble"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("Multiple input files are only supported by info and grz-metadata")
+    );
+
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args(["grz-validate"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No input file"));
+}
+
+#[test]
+fn should_show_grz_metadata_array_for_input_file_patterns() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("sample_R1.fastq"), R1).unwrap();
+
+    let output = fastq_tools()
+        .current_dir(dir.path())
+        .args(["--input", "sample_R1.fastq", "grz-metadata"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with('{'));
+
+    for input in [
+        vec!["--input", "sample_*.fastq"],
+        vec!["--input", "sample_R1.fastq", "--input", "sample_R1.fastq"],
+    ] {
+        let output = fastq_tools()
+            .current_dir(dir.path())
+            .args(input)
+            .arg("grz-metadata")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        assert!(String::from_utf8_lossy(&output.stdout).starts_with('['));
+    }
+}